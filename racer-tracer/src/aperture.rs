@@ -0,0 +1,290 @@
+use std::{path::Path, sync::Arc};
+
+use image::{GrayImage, Pixel};
+
+use crate::{
+    error::TracerError,
+    util::{degrees_to_radians, random_double, random_in_unit_disk, random_int_range},
+    vec3::Vec3,
+};
+
+// Tries before giving up on finding a point inside both the aperture
+// and the vignetting circle.
+const MAX_SAMPLE_TRIES: usize = 64;
+
+#[derive(Clone)]
+enum Shape {
+    Circle,
+    Polygon {
+        corners: Vec<Vec3>,
+    },
+    // Brighter pixels let more light through. The cumulative
+    // brightness of the pixels in row order is used to pick them.
+    // Pixels outside the circle touching the edges of the mask let
+    // nothing through so the lens is no bigger than the others.
+    Mask {
+        width: u32,
+        height: u32,
+        cdf: Arc<Vec<f64>>,
+    },
+}
+
+impl Shape {
+    // None if the mask is completely black inside the circle.
+    fn mask(mask: &GrayImage) -> Option<Self> {
+        let (width, height) = (f64::from(mask.width()), f64::from(mask.height()));
+        let cdf = mask
+            .enumerate_pixels()
+            .scan(0.0, |sum, (x, y, p)| {
+                let center = Vec3::new(
+                    (f64::from(x) + 0.5) / width * 2.0 - 1.0,
+                    1.0 - (f64::from(y) + 0.5) / height * 2.0,
+                    0.0,
+                );
+                if center.length_squared() <= 1.0 {
+                    *sum += f64::from(p.channels()[0]);
+                }
+                Some(*sum)
+            })
+            .collect::<Vec<f64>>();
+        (cdf.last().copied().unwrap_or_default() > 0.0).then(|| Shape::Mask {
+            width: mask.width(),
+            height: mask.height(),
+            cdf: Arc::new(cdf),
+        })
+    }
+}
+
+// The shape of the lens opening. This is what gives defocused
+// highlights their shape. Points are sampled within the unit disk
+// and get scaled by the lens radius by the camera.
+#[derive(Clone)]
+pub struct ApertureShape {
+    shape: Shape,
+    // How far the vignetting circle moves towards the edge of the
+    // frame. Cuts off the aperture near the frame edges which gives
+    // cat's eye shaped highlights.
+    vignetting: f64,
+}
+
+impl ApertureShape {
+    pub fn try_new(
+        blades: Option<usize>,
+        rotation: f64,
+        mask: Option<&Path>,
+        vignetting: f64,
+    ) -> Result<Self, TracerError> {
+        // The vignetting circle is moved this far out in the corners of
+        // the frame. From 2 lens radii on it misses the lens entirely.
+        if !(0.0..std::f64::consts::SQRT_2).contains(&vignetting) {
+            return Err(TracerError::InvalidAperture(format!(
+                "Optical vignetting has to be at least 0 and below {:.3}, got {}.",
+                std::f64::consts::SQRT_2,
+                vignetting
+            )));
+        }
+
+        let shape = match (mask, blades) {
+            (Some(path), _) => image::open(path)
+                .map_err(|e| {
+                    TracerError::FailedToOpenImage(
+                        path.to_string_lossy().into_owned(),
+                        e.to_string(),
+                    )
+                })
+                .map(|v| v.into_luma8())
+                .and_then(|mask| {
+                    Shape::mask(&mask).ok_or_else(|| {
+                        TracerError::FailedToOpenImage(
+                            path.to_string_lossy().into_owned(),
+                            String::from("Aperture mask is completely black."),
+                        )
+                    })
+                })?,
+            (None, Some(blades)) if blades >= 3 => {
+                let rotation = degrees_to_radians(rotation);
+                Shape::Polygon {
+                    corners: (0..blades)
+                        .map(|blade| {
+                            let angle = rotation
+                                + 2.0 * std::f64::consts::PI * blade as f64 / blades as f64;
+                            Vec3::new(angle.cos(), angle.sin(), 0.0)
+                        })
+                        .collect(),
+                }
+            }
+            _ => Shape::Circle,
+        };
+
+        Ok(Self { shape, vignetting })
+    }
+
+    fn sample_shape(&self) -> Vec3 {
+        match &self.shape {
+            Shape::Circle => random_in_unit_disk(),
+            Shape::Polygon { corners } => {
+                // Pick one of the triangles making up the polygon and
+                // a uniform point inside of it.
+                let blade = random_int_range(0, corners.len() as i32) as usize;
+                let mut a = random_double();
+                let mut b = random_double();
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                a * corners[blade] + b * corners[(blade + 1) % corners.len()]
+            }
+            Shape::Mask { width, height, cdf } => {
+                // A pixel picked by its brightness and a uniform point
+                // inside of it.
+                let total = cdf[cdf.len() - 1];
+                let target = random_double() * total;
+                let index = cdf.partition_point(|sum| *sum <= target).min(cdf.len() - 1);
+                let point = |dx: f64, dy: f64| {
+                    let x = ((index as u32 % width) as f64 + dx) / *width as f64;
+                    let y = ((index as u32 / width) as f64 + dy) / *height as f64;
+                    Vec3::new(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0)
+                };
+                // Pixels on the edge of the circle are partly outside
+                // of it. Their center is always inside.
+                (0..MAX_SAMPLE_TRIES)
+                    .map(|_| point(random_double(), random_double()))
+                    .find(|p| p.length_squared() <= 1.0)
+                    .unwrap_or_else(|| point(0.5, 0.5))
+            }
+        }
+    }
+
+    // Samples a point on the lens for a ray going through the screen
+    // coordinate u, v.
+    pub fn sample(&self, u: f64, v: f64) -> Vec3 {
+        if self.vignetting <= 0.0 {
+            return self.sample_shape();
+        }
+
+        let offset = Vec3::new(
+            self.vignetting * (2.0 * u - 1.0),
+            self.vignetting * (1.0 - 2.0 * v),
+            0.0,
+        );
+        // Barely anything of the lens is visible far out so the point
+        // closest to the vignetting circle is used when none of the
+        // tries made it inside.
+        let mut nearest = self.sample_shape();
+        for _ in 0..MAX_SAMPLE_TRIES {
+            let point = self.sample_shape();
+            if (point - offset).length_squared() <= 1.0 {
+                return point;
+            }
+            if (point - offset).length_squared() < (nearest - offset).length_squared() {
+                nearest = point;
+            }
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside() {
+        let aperture = ApertureShape::try_new(Some(6), 0.0, None, 0.0).unwrap();
+        // Distance from the center to the edges of a hexagon.
+        let inner = (std::f64::consts::PI / 6.0).cos();
+        for _ in 0..1000 {
+            let point = aperture.sample(0.5, 0.5);
+            let angle = point
+                .y()
+                .atan2(*point.x())
+                .rem_euclid(std::f64::consts::PI / 3.0);
+            let edge = inner / (angle - std::f64::consts::PI / 6.0).cos();
+            assert!(point.length() <= edge + 1e-9);
+        }
+    }
+
+    #[test]
+    fn mask_samples_lit_pixels_only() {
+        // Only the top right quarter lets light through.
+        let mask = GrayImage::from_fn(4, 4, |x, y| Luma([if x >= 2 && y < 2 { 255 } else { 0 }]));
+        let aperture = ApertureShape {
+            shape: Shape::mask(&mask).unwrap(),
+            vignetting: 0.0,
+        };
+        for _ in 0..1000 {
+            let point = aperture.sample(0.5, 0.5);
+            assert!(*point.x() >= 0.0 && *point.y() >= 0.0);
+        }
+        assert!(Shape::mask(&GrayImage::new(4, 4)).is_none());
+    }
+
+    #[test]
+    fn mask_fits_the_unit_disk() {
+        let white = GrayImage::from_pixel(8, 8, Luma([255]));
+        let aperture = ApertureShape {
+            shape: Shape::mask(&white).unwrap(),
+            vignetting: 0.0,
+        };
+        let samples = (0..1000)
+            .map(|_| aperture.sample(0.5, 0.5))
+            .collect::<Vec<Vec3>>();
+        assert!(samples.iter().all(|p| p.length_squared() <= 1.0));
+        assert!(samples.iter().any(|p| p.length_squared() > 0.8));
+        // Only the corners are lit which are all outside the circle.
+        let corners = GrayImage::from_fn(8, 8, |x, y| {
+            Luma([if (x == 0 || x == 7) && (y == 0 || y == 7) {
+                255
+            } else {
+                0
+            }])
+        });
+        assert!(Shape::mask(&corners).is_none());
+    }
+
+    #[test]
+    fn rejects_vignetting_cutting_off_the_lens() {
+        for vignetting in [1.5, -0.1, f64::NAN] {
+            assert!(matches!(
+                ApertureShape::try_new(None, 0.0, None, vignetting),
+                Err(TracerError::InvalidAperture(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn vignetting_falls_back_to_the_nearest_point() {
+        // Only the top left quarter is lit which never overlaps the
+        // vignetting circle in the bottom right corner of the frame.
+        let mask = GrayImage::from_fn(4, 4, |x, y| Luma([if x < 2 && y < 2 { 255 } else { 0 }]));
+        let aperture = ApertureShape {
+            shape: Shape::mask(&mask).unwrap(),
+            vignetting: 1.0,
+        };
+        let offset = Vec3::new(1.0, -1.0, 0.0);
+        for _ in 0..100 {
+            let point = aperture.sample(1.0, 1.0);
+            assert!(*point.x() <= 0.0 && *point.y() >= 0.0);
+            assert!((point - offset).length() < std::f64::consts::SQRT_2 + 0.5);
+        }
+    }
+
+    #[test]
+    fn vignetting_samples_stay_inside_the_offset_circle() {
+        let aperture = ApertureShape::try_new(None, 0.0, None, 1.0).unwrap();
+        let offset = Vec3::new(1.0, 1.0, 0.0);
+        let samples = (0..1000)
+            .map(|_| aperture.sample(1.0, 0.0))
+            .collect::<Vec<Vec3>>();
+        assert!(samples
+            .iter()
+            .all(|p| (*p - offset).length_squared() <= 1.0 && p.length_squared() <= 1.0));
+        // The corner of the frame still sees a spread of the lens
+        // rather than a single point.
+        assert!(samples
+            .iter()
+            .any(|p| (*p - samples[0]).length_squared() > 1e-6));
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use img::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use slog::Logger;
+
+use crate::{
+    camera::Camera,
+    error::TracerError,
+    image::Image,
+    renderer::RenderProgress,
+    scene::{ObjectCookie, Scene},
+    scene_controller::SceneController,
+    vec3::{Color, Vec3},
+};
+
+// How long a request waits for the application to handle it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// Requests are small JSON objects.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+// Anything missing is left as it is.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+pub struct CameraUpdate {
+    pos: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vfov: Option<f64>,
+    aperture: Option<f64>,
+    focus_distance: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ObjectUpdate {
+    pos: [f64; 3],
+}
+
+// Things that have to be done on the thread that owns the camera and
+// the scene.
+#[derive(Debug, PartialEq)]
+pub enum ApiCommand {
+    GetCamera,
+    SetCamera(CameraUpdate),
+    ListObjects,
+    MoveObject(usize, ObjectUpdate),
+    RemoveObject(usize),
+    Render,
+    Image,
+}
+
+#[derive(Debug)]
+pub enum ApiReply {
+    Json(Value),
+    Image(Vec<Color>),
+    Error(u16, String),
+}
+
+pub struct ApiRequest {
+    command: ApiCommand,
+    reply: Sender<ApiReply>,
+}
+
+impl ApiRequest {
+    // Carries out the command. Called from the update loop.
+    pub fn apply(
+        self,
+        camera: &mut Camera,
+        scene: &mut Scene,
+        scene_controller: &dyn SceneController,
+        rgb: &[Color],
+    ) {
+        let reply = apply(self.command, camera, scene, scene_controller, rgb);
+        // Nothing to do if the client is gone.
+        let _ = self.reply.send(reply);
+    }
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn array(v: &Vec3) -> [f64; 3] {
+    [*v.x(), *v.y(), *v.z()]
+}
+
+fn apply(
+    command: ApiCommand,
+    camera: &mut Camera,
+    scene: &mut Scene,
+    scene_controller: &dyn SceneController,
+    rgb: &[Color],
+) -> ApiReply {
+    let res = match command {
+        ApiCommand::GetCamera => Ok(json!({
+            "pos": array(&camera.pos()),
+            "forward": array(&camera.forward()),
+            "vfov": camera.get_vfov(),
+            "aperture": camera.get_aperture(),
+            "focus_distance": camera.get_focus_distance(),
+        })),
+        ApiCommand::SetCamera(update) => {
+            let res = update
+                .pos
+                .map_or(Ok(()), |pos| camera.set_pos(vec3(pos)))
+                .and_then(|_| {
+                    update
+                        .look_at
+                        .map_or(Ok(()), |look_at| camera.set_look_at(vec3(look_at)))
+                })
+                .and_then(|_| update.vfov.map_or(Ok(()), |vfov| camera.set_fov(vfov)))
+                .and_then(|_| {
+                    update
+                        .aperture
+                        .map_or(Ok(()), |aperture| camera.set_aperture(aperture))
+                })
+                .and_then(|_| {
+                    update
+                        .focus_distance
+                        .map_or(Ok(()), |distance| camera.set_focus_distance(distance))
+                });
+            res.map(|_| json!({}))
+        }
+        ApiCommand::ListObjects => Ok(Value::Array(
+            scene
+                .objects()
+                .iter()
+                .enumerate()
+                .map(|(id, object)| json!({ "id": id, "pos": array(&object.pos()) }))
+                .collect(),
+        )),
+        // Through the scene controller so the edits can be undone.
+        ApiCommand::MoveObject(id, update) => scene_controller
+            .move_object(scene, &ObjectCookie { id }, vec3(update.pos))
+            .map(|_| json!({})),
+        ApiCommand::RemoveObject(id) => scene_controller
+            .remove_object(scene, &ObjectCookie { id })
+            .map(|_| json!({})),
+        ApiCommand::Render => scene_controller
+            .start_render(camera, scene)
+            .map(|_| json!({})),
+        ApiCommand::Image => return ApiReply::Image(rgb.to_vec()),
+    };
+
+    match res {
+        Ok(value) => ApiReply::Json(value),
+        Err(e @ TracerError::NoObjectWithId(_)) => ApiReply::Error(404, e.to_string()),
+        Err(e) => ApiReply::Error(500, e.to_string()),
+    }
+}
+
+// Small HTTP server to control the application from other programs.
+// Requests are handed to the update loop through a channel except for
+// progress which can be answered right away.
+pub struct Api {
+    receiver: Mutex<Receiver<ApiRequest>>,
+}
+
+impl Api {
+    pub fn start(
+        log: Logger,
+        address: &str,
+        image: Image,
+        progress: Arc<RenderProgress>,
+    ) -> Result<Self, TracerError> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| TracerError::Api(format!("Failed to listen on {}: {}", address, e)))?;
+        info!(log, "Control API listening on http://{}", address);
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let res = stream
+                    .map_err(|e| TracerError::Api(e.to_string()))
+                    .and_then(|stream| serve(stream, &sender, &image, &progress));
+                if let Err(e) = res {
+                    warn!(log, "API request failed: {}", e);
+                }
+            }
+        });
+        Ok(Self {
+            receiver: Mutex::new(receiver),
+        })
+    }
+
+    pub fn requests(&self) -> Result<Vec<ApiRequest>, TracerError> {
+        self.receiver
+            .lock()
+            .map(|receiver| receiver.try_iter().collect())
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("api-receiver: {}", e)))
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<HttpRequest, ApiReply> {
+    let bad_request = |e: String| ApiReply::Error(400, e);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|e| bad_request(e.to_string()))?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(bad_request(format!("Bad request line: {}", line.trim()))),
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .map_err(|e| bad_request(e.to_string()))?;
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad_request(format!("Bad content length: {}", value.trim())))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(ApiReply::Error(
+            413,
+            format!(
+                "Body of {} bytes is larger than {} bytes",
+                content_length, MAX_BODY_SIZE
+            ),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| bad_request(e.to_string()))?;
+    Ok(HttpRequest { method, path, body })
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, ApiReply> {
+    serde_json::from_slice(body).map_err(|e| ApiReply::Error(400, e.to_string()))
+}
+
+fn object_id(id: &str) -> Result<usize, ApiReply> {
+    id.parse()
+        .map_err(|_| ApiReply::Error(400, format!("Bad object id: {}", id)))
+}
+
+// Everything except progress has to go through the update loop.
+fn route(request: &HttpRequest, progress: &RenderProgress) -> Result<ApiCommand, ApiReply> {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<&str>>();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["progress"]) => {
+            let info = progress.info();
+            Err(ApiReply::Json(json!({
+                "rendering": info.rendering,
+                "final_render": info.final_render,
+                "fraction": info.fraction(),
+                "samples": info.samples,
+                "samples_done": info.samples_done(),
+                "elapsed_seconds": info.elapsed.as_secs_f64(),
+                "eta_seconds": info.eta().map(|eta| eta.as_secs_f64()),
+            })))
+        }
+        ("GET", ["camera"]) => Ok(ApiCommand::GetCamera),
+        ("POST", ["camera"]) => parse_body(&request.body).map(ApiCommand::SetCamera),
+        ("GET", ["objects"]) => Ok(ApiCommand::ListObjects),
+        ("POST", ["objects", id]) => object_id(id).and_then(|id| {
+            parse_body(&request.body).map(|update| ApiCommand::MoveObject(id, update))
+        }),
+        ("DELETE", ["objects", id]) => object_id(id).map(ApiCommand::RemoveObject),
+        ("POST", ["render"]) => Ok(ApiCommand::Render),
+        ("GET", ["image"]) => Ok(ApiCommand::Image),
+        _ => Err(ApiReply::Error(
+            404,
+            format!("No such endpoint: {} {}", request.method, request.path),
+        )),
+    }
+}
+
+fn encode_png(rgb: &[Color], image: &Image) -> Result<Vec<u8>, String> {
+    let data = rgb
+        .iter()
+        .flat_map(|c| [c.x(), c.y(), c.z()])
+        .map(|v| (v.clamp(0.0, 1.0) * 255.0) as u8)
+        .collect::<Vec<u8>>();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(
+            &data,
+            image.width as u32,
+            image.height as u32,
+            ColorType::Rgb8,
+        )
+        .map(|_| png)
+        .map_err(|e| e.to_string())
+}
+
+fn serve(
+    mut stream: TcpStream,
+    sender: &Sender<ApiRequest>,
+    image: &Image,
+    progress: &RenderProgress,
+) -> Result<(), TracerError> {
+    let error = |e: std::io::Error| TracerError::Api(e.to_string());
+    stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(error)?;
+    let request = read_request(&mut BufReader::new(&stream));
+
+    let reply = match request {
+        Ok(request) => match route(&request, progress) {
+            Ok(command) => {
+                let (reply_sender, reply_receiver) = mpsc::channel();
+                sender
+                    .send(ApiRequest {
+                        command,
+                        reply: reply_sender,
+                    })
+                    .map_err(|e| TracerError::Api(e.to_string()))?;
+                reply_receiver
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|e| ApiReply::Error(503, e.to_string()))
+            }
+            Err(reply) => reply,
+        },
+        Err(reply) => reply,
+    };
+
+    let (status, content_type, body) = match reply {
+        ApiReply::Json(value) => (200, "application/json", value.to_string().into_bytes()),
+        ApiReply::Image(rgb) => match encode_png(&rgb, image) {
+            Ok(png) => (200, "image/png", png),
+            Err(e) => (
+                500,
+                "application/json",
+                json!({ "error": e }).to_string().into_bytes(),
+            ),
+        },
+        ApiReply::Error(status, e) => (
+            status,
+            "application/json",
+            json!({ "error": e }).to_string().into_bytes(),
+        ),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                reason,
+                content_type,
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .and_then(|_| stream.write_all(&body))
+        .map_err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &str) -> HttpRequest {
+        read_request(&mut raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn routes() {
+        let progress = RenderProgress::new();
+        let route = |raw: &str| route(&request(raw), &progress);
+
+        assert!(matches!(
+            route("GET /camera HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Ok(ApiCommand::GetCamera)
+        ));
+        assert_eq!(
+            route("POST /camera HTTP/1.1\r\nContent-Length: 15\r\n\r\n{\"vfov\": 30.0}\n").ok(),
+            Some(ApiCommand::SetCamera(CameraUpdate {
+                vfov: Some(30.0),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            route("POST /objects/3 HTTP/1.1\r\ncontent-length: 18\r\n\r\n{\"pos\": [1, 2, 3]}")
+                .ok(),
+            Some(ApiCommand::MoveObject(
+                3,
+                ObjectUpdate {
+                    pos: [1.0, 2.0, 3.0]
+                }
+            ))
+        );
+        assert_eq!(
+            route("DELETE /objects/2 HTTP/1.1\r\n\r\n").ok(),
+            Some(ApiCommand::RemoveObject(2))
+        );
+        assert!(matches!(
+            route("GET /progress HTTP/1.1\r\n\r\n"),
+            Err(ApiReply::Json(_))
+        ));
+        assert!(matches!(
+            route("DELETE /objects/x HTTP/1.1\r\n\r\n"),
+            Err(ApiReply::Error(400, _))
+        ));
+        assert!(matches!(
+            route("GET /nothing HTTP/1.1\r\n\r\n"),
+            Err(ApiReply::Error(404, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_large_bodies() {
+        assert!(matches!(
+            read_request(
+                &mut "POST /camera HTTP/1.1\r\nContent-Length: 1048577\r\n\r\n".as_bytes()
+            ),
+            Err(ApiReply::Error(413, _))
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use crate::{
     data_bus::DataReader,
     error::TracerError,
     geometry::Hittable,
+    renderer::count_node_visit,
     scene::{SceneObject, SceneObjectEvent},
     util::random_int_range,
 };
@@ -116,6 +117,7 @@ impl Hittable for Node {
         t_min: f64,
         t_max: f64,
     ) -> Option<crate::geometry::HitRecord> {
+        count_node_visit();
         if !self.bounding_box(t_min, t_max).hit(ray, t_min, t_max) {
             return None;
         }
@@ -183,6 +185,10 @@ impl BoundingVolumeHirearchy {
                         self.objects.remove(id.id);
                         Ok(())
                     }
+                    SceneObjectEvent::Insert { id, object } => {
+                        self.objects.insert(id.id.min(self.objects.len()), object);
+                        Ok(())
+                    }
                     SceneObjectEvent::Pos { id, pos } => {
                         if let Some(obj) = self.objects.get_mut(id.id) {
                             obj.set_pos(pos);
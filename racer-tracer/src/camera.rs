@@ -1,9 +1,15 @@
-use crate::config::CameraConfig;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aperture::ApertureShape;
+use crate::config::{CameraConfig, ShutterCurveConfig};
 use crate::data_bus::{DataBus, DataReader, DataWriter};
 use crate::error::TracerError;
 use crate::image::Image;
 use crate::ray::Ray;
-use crate::util::{degrees_to_radians, random_double_range, random_in_unit_disk};
+use crate::shutter::Shutter;
+use crate::util::{degrees_to_radians, random_double};
 use crate::vec3::Vec3;
 
 #[derive(Clone)]
@@ -67,8 +73,26 @@ pub struct CameraSharedData {
     viewport_height: f64,
     lens_radius: f64,
     focus_distance: f64,
-    time_a: f64,
-    time_b: f64,
+    shutter: Shutter,
+    aperture_shape: ApertureShape,
+}
+
+impl CameraSharedData {
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            pos: self.origin,
+            forward: self.forward,
+            vfov: self.vfov,
+            aperture: self.lens_radius * 2.0,
+            focus_distance: self.focus_distance,
+        }
+    }
+
+    // Distance to the point along the view direction. This is what
+    // the focus distance is measured in.
+    pub fn view_distance(&self, point: &Vec3) -> f64 {
+        (point - self.origin).dot(&-self.forward)
+    }
 }
 
 impl SharedCamera {
@@ -179,8 +203,29 @@ pub struct CameraInitData {
     pub aperture: f64,
     pub focus_distance: f64,
     pub aspect_ratio: f64,
-    pub time_a: f64,
-    pub time_b: f64,
+    pub shutter: Shutter,
+    pub aperture_shape: ApertureShape,
+}
+
+impl CameraInitData {
+    pub fn try_new(data: &CameraData, image: &Image) -> Result<Self, TracerError> {
+        Ok(Self {
+            look_from: data.pos,
+            look_at: data.look_at,
+            scene_up: Vec3::new(0.0, 1.0, 0.0),
+            vfov: data.vfov,
+            aperture: data.aperture,
+            focus_distance: data.focus_distance,
+            aspect_ratio: image.aspect_ratio,
+            shutter: Shutter::try_new(data.shutter_open, data.shutter_close, &data.shutter_curve)?,
+            aperture_shape: ApertureShape::try_new(
+                data.aperture_blades,
+                data.aperture_rotation,
+                data.aperture_mask.as_deref(),
+                data.optical_vignetting,
+            )?,
+        })
+    }
 }
 
 pub struct Camera {
@@ -224,8 +269,8 @@ impl Camera {
                 vfov: init.vfov,
                 lens_radius: init.aperture * 0.5,
                 focus_distance: init.focus_distance,
-                time_a: init.time_a,
-                time_b: init.time_b,
+                shutter: init.shutter,
+                aperture_shape: init.aperture_shape,
             },
             aspect_ratio: init.aspect_ratio,
             scene_up: init.scene_up,
@@ -237,10 +282,32 @@ impl Camera {
         self.bus.update()
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            pos: self.data.origin,
+            forward: self.data.forward,
+            vfov: self.data.vfov,
+            aperture: self.aperture,
+            focus_distance: self.data.focus_distance,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) -> Result<(), TracerError> {
+        self.set_pos(state.pos)
+            .and_then(|_| self.set_look_at(state.pos - state.forward))
+            .and_then(|_| self.set_fov(state.vfov))
+            .and_then(|_| self.set_aperture(state.aperture))
+            .and_then(|_| self.set_focus_distance(state.focus_distance))
+    }
+
     pub fn get_shared_camera(&mut self) -> SharedCamera {
         SharedCamera::new(self.data.clone(), self.bus.get_reader())
     }
 
+    pub fn pos(&self) -> Vec3 {
+        self.data.origin
+    }
+
     pub fn forward(&self) -> Vec3 {
         self.data.forward
     }
@@ -262,7 +329,6 @@ impl Camera {
         })
     }
 
-    #[allow(dead_code)]
     pub fn set_look_at(&mut self, look_at: Vec3) -> Result<(), TracerError> {
         self.data.forward = (self.data.origin - look_at).unit_vector();
         self.update_directions();
@@ -272,7 +338,7 @@ impl Camera {
             up: self.data.up,
             horizontal: self.data.horizontal,
             vertical: self.data.vertical,
-            upper_left_corner: self.data.vertical,
+            upper_left_corner: self.data.upper_left_corner,
         })
     }
 
@@ -323,8 +389,25 @@ impl Camera {
         self.data.focus_distance
     }
 
+    pub fn view_distance(&self, point: &Vec3) -> f64 {
+        self.data.view_distance(point)
+    }
+
+    // Screen coordinate (u, v) where the point ends up. None if the
+    // point is behind the camera.
+    pub fn project(&self, point: &Vec3) -> Option<(f64, f64)> {
+        let distance = self.data.view_distance(point);
+        (distance > 0.0).then(|| {
+            let to_point = point - self.data.origin;
+            (
+                0.5 + to_point.dot(&self.data.right) / (distance * self.data.viewport_width),
+                0.5 - to_point.dot(&self.data.up) / (distance * self.data.viewport_height),
+            )
+        })
+    }
+
     pub fn get_ray(camera_data: &CameraSharedData, u: f64, v: f64) -> Ray {
-        let ray_direction = camera_data.lens_radius * random_in_unit_disk();
+        let ray_direction = camera_data.lens_radius * camera_data.aperture_shape.sample(u, v);
         let offset = camera_data.right * ray_direction.x() + camera_data.up * ray_direction.y();
         Ray::new(
             camera_data.origin + offset,
@@ -332,7 +415,19 @@ impl Camera {
                 - v * camera_data.vertical
                 - camera_data.origin
                 - offset,
-            random_double_range(camera_data.time_a, camera_data.time_b),
+            camera_data.shutter.sample(random_double()),
+        )
+    }
+
+    // Ray without any depth of field or motion blur. Used when
+    // picking things on the screen.
+    pub fn get_pinhole_ray(camera_data: &CameraSharedData, u: f64, v: f64) -> Ray {
+        Ray::new(
+            camera_data.origin,
+            camera_data.upper_left_corner + u * camera_data.horizontal
+                - v * camera_data.vertical
+                - camera_data.origin,
+            camera_data.shutter.open(),
         )
     }
 
@@ -359,6 +454,38 @@ impl Camera {
         })
     }
 
+    // Moves the camera around the pivot while looking at it.
+    pub fn orbit(
+        &mut self,
+        pivot: &Vec3,
+        right_move: f64,
+        up_move: f64,
+    ) -> Result<(), TracerError> {
+        let mut offset = self.data.origin - pivot;
+        offset.rotate(right_move, &self.scene_up);
+
+        let mut right = self.data.right;
+        right.rotate(right_move, &self.scene_up);
+        let mut pitched = offset;
+        pitched.rotate(up_move, &right);
+
+        // Stop before going over the top since the camera would flip.
+        if pitched.unit_vector().dot(&self.scene_up).abs() < 0.99 {
+            offset = pitched;
+        }
+
+        self.set_pos(pivot + offset)
+            .and_then(|_| self.set_look_at(*pivot))
+    }
+
+    // Moves the camera towards or away from the pivot. The distance
+    // to the pivot is scaled by the factor.
+    pub fn dolly(&mut self, pivot: &Vec3, factor: f64) -> Result<(), TracerError> {
+        let offset = self.data.origin - pivot;
+        let distance = (offset.length() * factor).max(0.001);
+        self.set_pos(pivot + offset.unit_vector() * distance)
+    }
+
     #[allow(dead_code)]
     pub fn rotate_right(&mut self, right_move: f64) -> Result<(), TracerError> {
         self.rotate(right_move, 0.0)
@@ -390,6 +517,16 @@ impl Camera {
     }
 }
 
+// Everything needed to bring the camera back to an earlier view.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CameraState {
+    pos: Vec3,
+    forward: Vec3,
+    vfov: f64,
+    aperture: f64,
+    focus_distance: f64,
+}
+
 pub struct CameraData {
     pub vfov: f64,
     pub aperture: f64,
@@ -398,6 +535,13 @@ pub struct CameraData {
     pub look_at: Vec3,
     pub speed: f64,
     pub sensitivity: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub shutter_curve: ShutterCurveConfig,
+    pub aperture_blades: Option<usize>,
+    pub aperture_rotation: f64,
+    pub aperture_mask: Option<PathBuf>,
+    pub optical_vignetting: f64,
 }
 
 impl CameraData {
@@ -431,6 +575,28 @@ impl CameraData {
                 .sensitivity
                 .or(data2.sensitivity)
                 .unwrap_or_else(CameraData::default_sensitivity),
+            shutter_open: data1
+                .shutter_open
+                .or(data2.shutter_open)
+                .unwrap_or_else(CameraData::default_shutter_open),
+            shutter_close: data1
+                .shutter_close
+                .or(data2.shutter_close)
+                .unwrap_or_else(CameraData::default_shutter_close),
+            shutter_curve: data1
+                .shutter_curve
+                .or(data2.shutter_curve)
+                .unwrap_or_default(),
+            aperture_blades: data1.aperture_blades.or(data2.aperture_blades),
+            aperture_rotation: data1
+                .aperture_rotation
+                .or(data2.aperture_rotation)
+                .unwrap_or_else(CameraData::default_aperture_rotation),
+            aperture_mask: data1.aperture_mask.or(data2.aperture_mask),
+            optical_vignetting: data1
+                .optical_vignetting
+                .or(data2.optical_vignetting)
+                .unwrap_or_else(CameraData::default_optical_vignetting),
         }
     }
 
@@ -461,4 +627,20 @@ impl CameraData {
     pub fn default_sensitivity() -> f64 {
         0.001
     }
+
+    pub fn default_shutter_open() -> f64 {
+        0.0
+    }
+
+    pub fn default_shutter_close() -> f64 {
+        1.0
+    }
+
+    pub fn default_aperture_rotation() -> f64 {
+        0.0
+    }
+
+    pub fn default_optical_vignetting() -> f64 {
+        0.0
+    }
 }
@@ -0,0 +1,405 @@
+use std::{fmt, path::Path};
+
+use img::DynamicImage;
+
+use crate::{
+    config::CompareMetric, error::TracerError, image::Image, post_process::output::srgb_to_linear,
+    vec3::Color,
+};
+
+// Keeps relMSE from blowing up where the reference is black.
+const REL_MSE_EPSILON: f64 = 0.01;
+
+// How much the perceptual error blurs the colors before comparing
+// them, in pixels. The eye picks up less detail in color than in
+// brightness.
+const LUMINANCE_BLUR: f64 = 0.5;
+const CHROMA_BLUR: f64 = 1.0;
+
+// The color error is raised to this before it is normalized, like
+// FLIP does.
+const COLOR_EXPONENT: f64 = 0.7;
+const FEATURE_EXPONENT: f64 = 0.5;
+
+// Color errors up to this part of the largest one are stretched to
+// cover most of the range. Small differences matter more than how
+// large the large ones are.
+const COLOR_KNEE: f64 = 0.4;
+const COLOR_KNEE_ERROR: f64 = 0.95;
+
+// D65 white in XYZ.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+// Colors of the difference image going from no error to the most.
+const FALSE_COLORS: [(f64, f64, f64); 5] = [
+    (0.0, 0.0, 0.02),
+    (0.32, 0.07, 0.5),
+    (0.72, 0.21, 0.47),
+    (0.99, 0.53, 0.38),
+    (0.99, 0.99, 0.75),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    pub mse: f64,
+    pub rel_mse: f64,
+    // Decibels with 1 as the peak value. Infinite for equal images.
+    pub psnr: f64,
+    // Mean perceptual error between 0 and 1.
+    pub flip: f64,
+}
+
+impl Metrics {
+    pub fn get(&self, metric: &CompareMetric) -> f64 {
+        match metric {
+            CompareMetric::Mse => self.mse,
+            CompareMetric::RelMse => self.rel_mse,
+            CompareMetric::Psnr => self.psnr,
+            CompareMetric::Flip => self.flip,
+        }
+    }
+
+    // PSNR gets better as it goes up, the others as they go down.
+    pub fn exceeds(&self, metric: &CompareMetric, threshold: f64) -> bool {
+        match metric {
+            CompareMetric::Psnr => self.psnr < threshold,
+            _ => self.get(metric) > threshold,
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MSE: {:.6} relMSE: {:.6} PSNR: {:.2} dB FLIP: {:.4}",
+            self.mse, self.rel_mse, self.psnr, self.flip
+        )
+    }
+}
+
+pub struct Comparison {
+    pub metrics: Metrics,
+    // Perceptual error of each pixel.
+    pub error: Vec<f64>,
+}
+
+impl Comparison {
+    // The error of every pixel as RGB bytes.
+    pub fn false_color_rgb(&self) -> Vec<u8> {
+        self.error
+            .iter()
+            .flat_map(|e| {
+                let c = false_color(*e);
+                [c.x(), c.y(), c.z()].map(|v| (v * 255.0).round() as u8)
+            })
+            .collect()
+    }
+}
+
+// Display value for an error between 0 and 1.
+pub fn false_color(error: f64) -> Color {
+    let t = error.clamp(0.0, 1.0) * (FALSE_COLORS.len() - 1) as f64;
+    let i = (t as usize).min(FALSE_COLORS.len() - 2);
+    let f = t - i as f64;
+    let (a, b) = (FALSE_COLORS[i], FALSE_COLORS[i + 1]);
+    Color::new(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+// Loads a png or exr as linear colors.
+pub fn load(path: &Path) -> Result<(Image, Vec<Color>), TracerError> {
+    img::open(path)
+        .map_err(|e| {
+            TracerError::FailedToOpenImage(path.to_string_lossy().into_owned(), e.to_string())
+        })
+        .map(|v| {
+            let linear = matches!(
+                v,
+                DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+            );
+            let v = v.into_rgb32f();
+            let decode = |c: f32| match linear {
+                true => f64::from(c),
+                false => srgb_to_linear(f64::from(c)),
+            };
+            (
+                Image::new(v.width() as usize, v.height() as usize),
+                v.pixels()
+                    .map(|p| Color::new(decode(p.0[0]), decode(p.0[1]), decode(p.0[2])))
+                    .collect(),
+            )
+        })
+}
+
+// Saves the error of each pixel in false colors.
+pub fn save_difference(
+    path: &Path,
+    image: &Image,
+    comparison: &Comparison,
+) -> Result<(), TracerError> {
+    img::RgbImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        comparison.false_color_rgb(),
+    )
+    .ok_or_else(|| TracerError::ImageSave(String::from("Image has the wrong size")))
+    .and_then(|buffer| {
+        buffer
+            .save(path)
+            .map_err(|e| TracerError::ImageSave(e.to_string()))
+    })
+}
+
+// Compares the linear image a with the reference b. MSE, relMSE and
+// PSNR use the linear values, the perceptual error compares them as
+// shown on screen.
+pub fn compare(image: &Image, a: &[Color], b: &[Color]) -> Comparison {
+    let (mut mse, mut rel_mse) = (0.0, 0.0);
+    for (a, b) in a.iter().zip(b) {
+        for i in 0..3 {
+            let d = (a[i] - b[i]) * (a[i] - b[i]);
+            mse += d;
+            rel_mse += d / (b[i] * b[i] + REL_MSE_EPSILON);
+        }
+    }
+    let samples = (3 * a.len().max(1)) as f64;
+    let (mse, rel_mse) = (mse / samples, rel_mse / samples);
+
+    let error = flip(image, a, b);
+    let flip = error.iter().sum::<f64>() / error.len().max(1) as f64;
+    Comparison {
+        metrics: Metrics {
+            mse,
+            rel_mse,
+            psnr: -10.0 * mse.log10(),
+            flip,
+        },
+        error,
+    }
+}
+
+pub fn compare_files(a: &Path, b: &Path) -> Result<(Image, Comparison), TracerError> {
+    let (image, a_data) = load(a)?;
+    let (reference, b_data) = load(b)?;
+    if image.width != reference.width || image.height != reference.height {
+        return Err(TracerError::Compare(format!(
+            "{} is {}x{} but {} is {}x{}",
+            a.display(),
+            image.width,
+            image.height,
+            b.display(),
+            reference.width,
+            reference.height
+        )));
+    }
+    let comparison = compare(&image, &a_data, &b_data);
+    Ok((image, comparison))
+}
+
+// Colors are clamped to what a display can show.
+fn to_xyz(color: &Color) -> [f64; 3] {
+    let (r, g, b) = (
+        color.x().clamp(0.0, 1.0),
+        color.y().clamp(0.0, 1.0),
+        color.z().clamp(0.0, 1.0),
+    );
+    [
+        (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE[0],
+        (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE[1],
+        (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE[2],
+    ]
+}
+
+// Linear version of CIELAB that can be blurred, called YyCxCz.
+fn to_yycxcz(xyz: [f64; 3]) -> [f64; 3] {
+    [
+        116.0 * xyz[1] - 16.0,
+        500.0 * (xyz[0] - xyz[1]),
+        200.0 * (xyz[1] - xyz[2]),
+    ]
+}
+
+fn yycxcz_to_lab(c: [f64; 3]) -> [f64; 3] {
+    let y = (c[0] + 16.0) / 116.0;
+    let (x, z) = (c[1] / 500.0 + y, y - c[2] / 200.0);
+    let f = |t: f64| {
+        let delta: f64 = 6.0 / 29.0;
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    [
+        116.0 * f(y) - 16.0,
+        500.0 * (f(x) - f(y)),
+        200.0 * (f(y) - f(z)),
+    ]
+}
+
+// Distance that works better than euclidean for large differences.
+fn hyab(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (a[0] - b[0]).abs() + ((a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn blur(image: &Image, data: &[f64], sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let weights = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<f64>>();
+    let total: f64 = weights.iter().sum();
+    let (w, h) = (image.width as isize, image.height as isize);
+    let pass = |data: &[f64], dx: isize, dy: isize| {
+        (0..data.len())
+            .map(|i| {
+                let (x, y) = ((i % image.width) as isize, (i / image.width) as isize);
+                weights
+                    .iter()
+                    .zip(-radius..=radius)
+                    .map(|(weight, o)| {
+                        let x = (x + o * dx).clamp(0, w - 1);
+                        let y = (y + o * dy).clamp(0, h - 1);
+                        weight * data[(y * w + x) as usize]
+                    })
+                    .sum::<f64>()
+                    / total
+            })
+            .collect::<Vec<f64>>()
+    };
+    pass(&pass(data, 1, 0), 0, 1)
+}
+
+// Blurs each channel as much as the eye would and converts to CIELAB.
+fn filtered_lab(image: &Image, data: &[Color]) -> Vec<[f64; 3]> {
+    let yycxcz = data
+        .iter()
+        .map(|c| to_yycxcz(to_xyz(c)))
+        .collect::<Vec<_>>();
+    let channel = |i: usize, sigma: f64| {
+        blur(
+            image,
+            &yycxcz.iter().map(|c| c[i]).collect::<Vec<f64>>(),
+            sigma,
+        )
+    };
+    let (l, x, z) = (
+        channel(0, LUMINANCE_BLUR),
+        channel(1, CHROMA_BLUR),
+        channel(2, CHROMA_BLUR),
+    );
+    (0..data.len())
+        .map(|i| yycxcz_to_lab([l[i], x[i], z[i]]))
+        .collect()
+}
+
+// Gradient of the luminance at each pixel.
+fn edges(image: &Image, data: &[Color]) -> Vec<f64> {
+    let y = data.iter().map(|c| to_xyz(c)[1]).collect::<Vec<f64>>();
+    let (w, h) = (image.width as isize, image.height as isize);
+    let get = |x: isize, y_: isize| y[(y_.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+    (0..data.len())
+        .map(|i| {
+            let (x, y_) = ((i % image.width) as isize, (i / image.width) as isize);
+            let dx = (get(x + 1, y_) - get(x - 1, y_)) / 2.0;
+            let dy = (get(x, y_ + 1) - get(x, y_ - 1)) / 2.0;
+            (dx * dx + dy * dy).sqrt()
+        })
+        .collect()
+}
+
+// Per pixel error in the style of NVIDIA's FLIP. The color difference
+// of the blurred images is boosted where edges differ, so a missing or
+// moved edge counts even when the colors around it are close.
+fn flip(image: &Image, a: &[Color], b: &[Color]) -> Vec<f64> {
+    // Largest color difference, between green and blue.
+    let max_color = hyab(
+        &yycxcz_to_lab(to_yycxcz(to_xyz(&Color::new(0.0, 1.0, 0.0)))),
+        &yycxcz_to_lab(to_yycxcz(to_xyz(&Color::new(0.0, 0.0, 1.0)))),
+    )
+    .powf(COLOR_EXPONENT);
+
+    let (lab_a, lab_b) = (filtered_lab(image, a), filtered_lab(image, b));
+    let (edges_a, edges_b) = (edges(image, a), edges(image, b));
+    (0..a.len())
+        .map(|i| {
+            let color = (hyab(&lab_a[i], &lab_b[i]).powf(COLOR_EXPONENT) / max_color).min(1.0);
+            let color = if color < COLOR_KNEE {
+                color * COLOR_KNEE_ERROR / COLOR_KNEE
+            } else {
+                COLOR_KNEE_ERROR
+                    + (color - COLOR_KNEE) / (1.0 - COLOR_KNEE) * (1.0 - COLOR_KNEE_ERROR)
+            };
+            let feature = ((edges_a[i] - edges_b[i]).abs() / 2.0_f64.sqrt())
+                .powf(FEATURE_EXPONENT)
+                .min(1.0);
+            color.powf(1.0 - feature)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(image: &Image, scale: f64) -> Vec<Color> {
+        (0..image.width * image.height)
+            .map(|i| {
+                let v = (i % image.width) as f64 / image.width as f64 * scale;
+                Color::new(v, v * 0.5, 0.25)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn equal_images() {
+        let image = Image::new(16, 8);
+        let a = gradient(&image, 1.0);
+        let comparison = compare(&image, &a, &a);
+        assert_eq!(comparison.metrics.mse, 0.0);
+        assert_eq!(comparison.metrics.rel_mse, 0.0);
+        assert!(comparison.metrics.psnr.is_infinite());
+        assert_eq!(comparison.metrics.flip, 0.0);
+        assert!(!comparison.metrics.exceeds(&CompareMetric::Psnr, 40.0));
+    }
+
+    #[test]
+    fn larger_differences_score_worse() {
+        let image = Image::new(16, 8);
+        let reference = vec![Color::new(0.5, 0.5, 0.5); 16 * 8];
+        let close = vec![Color::new(0.6, 0.5, 0.5); 16 * 8];
+        let far = vec![Color::new(0.9, 0.1, 0.5); 16 * 8];
+
+        let close = compare(&image, &close, &reference).metrics;
+        assert!((close.mse - 0.01 / 3.0).abs() < 1e-12);
+        assert!((close.rel_mse - 0.01 / 3.0 / 0.26).abs() < 1e-12);
+        assert!((close.psnr - 10.0 * 300.0_f64.log10()).abs() < 1e-9);
+
+        let far = compare(&image, &far, &reference).metrics;
+        assert!(far.mse > close.mse);
+        assert!(far.psnr < close.psnr);
+        assert!(far.flip > close.flip && close.flip > 0.0);
+        assert!(far.exceeds(&CompareMetric::Flip, close.flip));
+        assert!(!close.exceeds(&CompareMetric::Flip, far.flip));
+    }
+
+    #[test]
+    fn edges_count() {
+        // The same average color but with the edge in another place.
+        let image = Image::new(32, 8);
+        let edge = |at: usize| {
+            (0..32 * 8)
+                .map(|i| match i % 32 < at {
+                    true => Color::new(0.0, 0.0, 0.0),
+                    false => Color::new(1.0, 1.0, 1.0),
+                })
+                .collect::<Vec<Color>>()
+        };
+        let comparison = compare(&image, &edge(16), &edge(17));
+        assert!(comparison.error[8 * 32 / 2 + 16] > 0.5);
+        assert_eq!(comparison.error[8 * 32 / 2 + 2], 0.0);
+    }
+}
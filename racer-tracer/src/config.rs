@@ -1,7 +1,7 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
 use config::File;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use structopt::StructOpt;
 
 use crate::{
@@ -25,6 +25,76 @@ pub struct Args {
 
     #[structopt(long = "image-action")]
     pub image_action: Option<ImageActionConfig>,
+
+    // Continue the final render from its checkpoint if there is one.
+    #[structopt(long = "resume")]
+    pub resume: bool,
+
+    // Image to compare the render with in the split view.
+    #[structopt(long = "reference")]
+    pub reference: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum Command {
+    // Renders tiles for other racer-tracer instances.
+    Worker {
+        #[structopt(long = "listen")]
+        listen: String,
+    },
+    // Compares an image with a reference image. Fails when they differ
+    // more than the threshold.
+    Compare {
+        image: PathBuf,
+        reference: PathBuf,
+        // Where to save the difference image. Next to the image by
+        // default.
+        #[structopt(long = "output")]
+        output: Option<PathBuf>,
+        #[structopt(long = "metric", default_value = "flip")]
+        metric: CompareMetric,
+        #[structopt(long = "threshold")]
+        threshold: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareMetric {
+    Mse,
+    RelMse,
+    Psnr,
+    Flip,
+}
+
+impl CompareMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompareMetric::Mse => "MSE",
+            CompareMetric::RelMse => "relMSE",
+            CompareMetric::Psnr => "PSNR",
+            CompareMetric::Flip => "FLIP",
+        }
+    }
+}
+
+impl FromStr for CompareMetric {
+    type Err = TracerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mse" => Ok(CompareMetric::Mse),
+            "relmse" => Ok(CompareMetric::RelMse),
+            "psnr" => Ok(CompareMetric::Psnr),
+            "flip" => Ok(CompareMetric::Flip),
+            _ => Err(TracerError::ArgumentParsingError(format!(
+                "Unknown metric {}, expected mse, relmse, psnr or flip",
+                s
+            ))),
+        }
+    }
 }
 
 impl TryFrom<Args> for Config {
@@ -32,7 +102,13 @@ impl TryFrom<Args> for Config {
     fn try_from(args: Args) -> Result<Self, TracerError> {
         Config::from_file(args.config).and_then(|mut cfg| {
             if let Some(image_action) = args.image_action {
-                cfg.image_action = image_action;
+                cfg.image_actions = vec![image_action];
+            }
+            cfg.resume = args.resume;
+            // Workers get the seed along with the rest of the config.
+            cfg.scene_seed = cfg.scene_seed.or_else(|| Some(rand::random()));
+            if args.reference.is_some() {
+                cfg.reference = args.reference;
             }
 
             if let Some(scene) = args.scene {
@@ -66,22 +142,33 @@ impl TryFrom<Args> for Config {
     }
 }
 
-#[derive(Default, Clone, Debug, Deserialize)]
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct ScreenConfig {
     pub height: usize,
     pub width: usize,
+
+    // Show the HUD overlay from the start.
+    #[serde(default)]
+    pub hud: bool,
 }
 
-#[derive(Default, Clone, Debug, Deserialize)]
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct RenderConfig {
     pub samples: usize,
     pub max_depth: usize,
-    pub num_threads_width: usize,
-    pub num_threads_height: usize,
     pub scale: usize,
+
+    // Width and height in pixels of the tiles the image is split into
+    // when rendering.
+    #[serde(default = "default_tile_size")]
+    pub tile_size: usize,
 }
 
-#[derive(StructOpt, Debug, Clone, Deserialize, Default)]
+fn default_tile_size() -> usize {
+    32
+}
+
+#[derive(StructOpt, Debug, Clone, Deserialize, Serialize, Default)]
 pub enum SceneLoaderConfig {
     #[default]
     None,
@@ -92,24 +179,48 @@ pub enum SceneLoaderConfig {
     Sandbox,
 }
 
-#[derive(StructOpt, Debug, Clone, Deserialize, Default)]
+#[derive(StructOpt, Debug, Clone, Deserialize, Serialize, Default)]
 pub enum ImageActionConfig {
     #[default]
     None,
     SavePng,
+    // Saves the image before tone mapping.
+    SaveExr,
+    // Runs a command with the files written so far in
+    // RACER_TRACER_OUTPUT and RACER_TRACER_OUTPUTS.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
 }
 
-#[derive(StructOpt, Debug, Clone, Deserialize, Default)]
+#[derive(StructOpt, Debug, Clone, Deserialize, Serialize, Default)]
 pub enum SceneControllerConfig {
     #[default]
     Interactive,
 }
 
-#[derive(StructOpt, Debug, Clone, Deserialize, Default)]
+#[derive(StructOpt, Debug, Clone, Deserialize, Serialize, Default)]
 pub enum RendererConfig {
     #[default]
     Cpu,
     CpuPreview,
+    // Hands out tiles to worker processes started with `racer-tracer
+    // worker --listen <addr>`.
+    Distributed {
+        workers: Vec<String>,
+        // Seconds to wait for a tile before the worker is given up on.
+        timeout: Option<u64>,
+    },
+}
+
+fn default_output_name() -> String {
+    String::from("{hash}")
+}
+
+fn default_checkpoint_interval() -> u64 {
+    30
 }
 
 fn default_preview() -> RendererConfig {
@@ -122,13 +233,14 @@ impl FromStr for ImageActionConfig {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "png" => Ok(ImageActionConfig::SavePng),
+            "exr" => Ok(ImageActionConfig::SaveExr),
             "none" => Ok(ImageActionConfig::None),
             _ => Ok(ImageActionConfig::None),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ColorMatrix {
     pub colors: [Color; 3],
 }
@@ -140,7 +252,7 @@ pub struct ColorMatrix {
 // a tone mapping technique without having to override any default
 // settings.
 // https://github.com/mehcode/config-rs/issues/126
-#[derive(Default, Debug, Clone, Deserialize)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub enum ToneMapConfig {
     Reinhard {
         default: Option<bool>,
@@ -162,11 +274,146 @@ pub enum ToneMapConfig {
         input_matrix: Option<ColorMatrix>,
         output_matrix: Option<ColorMatrix>,
     },
+    Agx {
+        default: Option<bool>,
+        look: Option<AgxLook>,
+    },
+    PbrNeutral {
+        default: Option<bool>,
+    },
     #[default]
     None,
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub enum AgxLook {
+    #[default]
+    Base,
+    Punchy,
+    Golden,
+}
+
+impl ToneMapConfig {
+    // Every tone map with its default settings.
+    pub fn all() -> Vec<ToneMapConfig> {
+        vec![
+            ToneMapConfig::Reinhard {
+                default: Some(true),
+                max_white: None,
+            },
+            ToneMapConfig::Hable {
+                default: Some(true),
+                shoulder_strength: None,
+                linear_strength: None,
+                linear_angle: None,
+                toe_strength: None,
+                toe_numerator: None,
+                toe_denominator: None,
+                exposure_bias: None,
+                linear_white_point: None,
+            },
+            ToneMapConfig::Aces {
+                default: Some(true),
+                input_matrix: None,
+                output_matrix: None,
+            },
+            ToneMapConfig::Agx {
+                default: Some(true),
+                look: None,
+            },
+            ToneMapConfig::PbrNeutral {
+                default: Some(true),
+            },
+            ToneMapConfig::None,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapConfig::Reinhard { .. } => "Reinhard",
+            ToneMapConfig::Hable { .. } => "Hable",
+            ToneMapConfig::Aces { .. } => "Aces",
+            ToneMapConfig::Agx { .. } => "Agx",
+            ToneMapConfig::PbrNeutral { .. } => "PbrNeutral",
+            ToneMapConfig::None => "None",
+        }
+    }
+}
+
+// A stage of the post process pipeline. Stages run in order on the
+// linear image.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum PostProcessConfig {
+    // Exposure in stops.
+    Exposure {
+        ev: f64,
+    },
+    // Temperature in kelvin of the light that should become white.
+    WhiteBalance {
+        temperature: f64,
+        tint: Option<f64>,
+    },
+    ColorGrade {
+        contrast: Option<f64>,
+        saturation: Option<f64>,
+    },
+    // A 3D LUT in the .cube format.
+    Lut {
+        path: PathBuf,
+    },
+    ToneMap(ToneMapConfig),
+    // Glow around bright parts of the image. Goes before the tone map.
+    Bloom {
+        default: Option<bool>,
+        threshold: Option<f64>,
+        intensity: Option<f64>,
+        radius: Option<f64>,
+        levels: Option<usize>,
+        glare: Option<GlareConfig>,
+    },
+    // Exposure from the luminance of the image. Low and high are the
+    // percentiles of the pixels that are averaged.
+    AutoExposure {
+        default: Option<bool>,
+        low: Option<f64>,
+        high: Option<f64>,
+        key: Option<f64>,
+        speed: Option<f64>,
+    },
+}
+
+// How the image is encoded for the display and saved images.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub enum OutputTransformConfig {
+    #[default]
+    Srgb,
+    Rec709,
+    // Display P3 colors with the sRGB curve.
+    DisplayP3,
+}
+
+// Star shaped streaks from bright parts of the image.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct GlareConfig {
+    pub streaks: Option<usize>,
+    // Pixels until the streak has mostly faded.
+    pub length: Option<f64>,
+    pub intensity: Option<f64>,
+    // Degrees.
+    pub rotation: Option<f64>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub enum ShutterCurveConfig {
+    #[default]
+    Box,
+    Triangle,
+    Custom {
+        samples: Vec<f64>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct CameraConfig {
     pub vfov: Option<f64>,
     pub aperture: Option<f64>,
@@ -175,9 +422,16 @@ pub struct CameraConfig {
     pub look_at: Option<Vec3>,
     pub speed: Option<f64>,
     pub sensitivity: Option<f64>,
+    pub shutter_open: Option<f64>,
+    pub shutter_close: Option<f64>,
+    pub shutter_curve: Option<ShutterCurveConfig>,
+    pub aperture_blades: Option<usize>,
+    pub aperture_rotation: Option<f64>,
+    pub aperture_mask: Option<PathBuf>,
+    pub optical_vignetting: Option<f64>,
 }
 
-#[derive(Default, Clone, Debug, Deserialize)]
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default)]
     pub preview: RenderConfig,
@@ -191,12 +445,40 @@ pub struct Config {
     #[serde(default)]
     pub loader: SceneLoaderConfig,
 
+    // Seed of the random scene. Picked at start when not set.
     #[serde(default)]
-    pub image_action: ImageActionConfig,
+    pub scene_seed: Option<u64>,
+
+    // Run in order after a final render completes. Older configs
+    // have a single image_action.
+    #[serde(default, alias = "image_action", deserialize_with = "one_or_many")]
+    pub image_actions: Vec<ImageActionConfig>,
 
     #[serde(default)]
     pub image_output_dir: Option<PathBuf>,
 
+    // Name of saved images relative to image_output_dir without the
+    // extension. See the readme for the placeholders.
+    #[serde(default = "default_output_name")]
+    pub output_name: String,
+
+    // Where checkpoints of final renders are kept. Defaults to the
+    // temp directory.
+    #[serde(default)]
+    pub checkpoint_dir: Option<PathBuf>,
+
+    // Seconds between checkpoints while rendering.
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u64,
+
+    // Address to serve the control API on, e.g. 127.0.0.1:8080.
+    #[serde(default)]
+    pub api: Option<String>,
+
+    // Set from the command line.
+    #[serde(skip)]
+    pub resume: bool,
+
     #[serde(default)]
     pub scene_controller: SceneControllerConfig,
 
@@ -211,6 +493,46 @@ pub struct Config {
 
     #[serde(default)]
     pub tone_map: ToneMapConfig,
+
+    // Applied to the image before it is shown and saved. When empty
+    // only tone_map is applied.
+    #[serde(default)]
+    pub post_process: Vec<PostProcessConfig>,
+
+    #[serde(default)]
+    pub output_transform: OutputTransformConfig,
+
+    // Tone maps to switch between while running. All of them with
+    // their defaults when empty.
+    #[serde(default)]
+    pub tone_maps: Vec<ToneMapConfig>,
+
+    // Image to compare the render with in the split view.
+    #[serde(default)]
+    pub reference: Option<PathBuf>,
+
+    // Action name to key or mouse button. Overrides the default
+    // bindings.
+    #[serde(default)]
+    pub controls: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    OneOrMany::deserialize(deserializer).map(|value| match value {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
 }
 
 impl Config {
@@ -223,3 +545,36 @@ impl Config {
             .map_err(|e| TracerError::Configuration(file, e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use config::FileFormat;
+
+    use super::*;
+
+    fn image_actions(yaml: &str) -> Vec<ImageActionConfig> {
+        config::Config::builder()
+            .add_source(File::from_str(yaml, FileFormat::Yaml))
+            .build()
+            .and_then(|c| c.try_deserialize::<Config>())
+            .unwrap()
+            .image_actions
+    }
+
+    #[test]
+    fn image_action_alias() {
+        assert!(matches!(
+            image_actions("image_action: SavePng").as_slice(),
+            [ImageActionConfig::SavePng]
+        ));
+        assert!(matches!(
+            image_actions("image_actions: [SavePng, SaveExr]").as_slice(),
+            [ImageActionConfig::SavePng, ImageActionConfig::SaveExr]
+        ));
+        assert!(matches!(
+            image_actions("image_action:\n  Command:\n    command: ls").as_slice(),
+            [ImageActionConfig::Command { .. }]
+        ));
+        assert!(image_actions("screen: { width: 1, height: 1 }").is_empty());
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+
+use minifb::{Key, MouseButton};
+
+use crate::{
+    error::TracerError,
+    key_inputs::{KeyEvent, ListenKeyEvents},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Select,
+    Delete,
+    Render,
+    Autofocus,
+    TrackFocus,
+    ToggleOrbit,
+    OrbitPivot,
+    FovUp,
+    FovDown,
+    ApertureUp,
+    ApertureDown,
+    FocusUp,
+    FocusDown,
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    ObjectLeft,
+    ObjectRight,
+    ObjectForward,
+    ObjectBack,
+    Look,
+    MoveObject,
+    Pan,
+    ToggleHud,
+    ToggleBoundingBox,
+    ToggleBloom,
+    CycleToneMap,
+    ToggleSplit,
+    Undo,
+    Redo,
+    SelectRegion,
+    ClearRegion,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ActionKind {
+    // Triggers once when the key is released.
+    Press,
+    // Triggers every update while the key is held down.
+    Hold,
+    // Triggers when the mouse moves with the button held down.
+    Drag,
+}
+
+// Name in the config, kind of action and the default binding.
+const ACTIONS: [(Action, &str, ActionKind, &str); 33] = [
+    (Action::Select, "select", ActionKind::Press, "Q"),
+    (Action::Delete, "delete", ActionKind::Press, "E"),
+    (Action::Render, "render", ActionKind::Press, "R"),
+    (Action::Autofocus, "autofocus", ActionKind::Press, "F"),
+    (Action::TrackFocus, "track_focus", ActionKind::Press, "T"),
+    (Action::ToggleOrbit, "toggle_orbit", ActionKind::Press, "O"),
+    (Action::OrbitPivot, "orbit_pivot", ActionKind::Press, "P"),
+    (Action::FovUp, "fov_up", ActionKind::Press, "NumPadMinus"),
+    (Action::FovDown, "fov_down", ActionKind::Press, "NumPadPlus"),
+    (
+        Action::ApertureUp,
+        "aperture_up",
+        ActionKind::Press,
+        "NumPad8",
+    ),
+    (
+        Action::ApertureDown,
+        "aperture_down",
+        ActionKind::Press,
+        "NumPad2",
+    ),
+    (Action::FocusUp, "focus_up", ActionKind::Press, "NumPad4"),
+    (
+        Action::FocusDown,
+        "focus_down",
+        ActionKind::Press,
+        "NumPad6",
+    ),
+    (Action::MoveForward, "move_forward", ActionKind::Hold, "W"),
+    (Action::MoveBack, "move_back", ActionKind::Hold, "S"),
+    (Action::MoveLeft, "move_left", ActionKind::Hold, "A"),
+    (Action::MoveRight, "move_right", ActionKind::Hold, "D"),
+    (Action::ObjectLeft, "object_left", ActionKind::Hold, "Left"),
+    (
+        Action::ObjectRight,
+        "object_right",
+        ActionKind::Hold,
+        "Right",
+    ),
+    (
+        Action::ObjectForward,
+        "object_forward",
+        ActionKind::Hold,
+        "Up",
+    ),
+    (Action::ObjectBack, "object_back", ActionKind::Hold, "Down"),
+    (Action::Look, "look", ActionKind::Drag, "MouseLeft"),
+    (
+        Action::MoveObject,
+        "move_object",
+        ActionKind::Drag,
+        "MouseRight",
+    ),
+    (Action::Pan, "pan", ActionKind::Drag, "MouseMiddle"),
+    (Action::ToggleHud, "toggle_hud", ActionKind::Press, "H"),
+    (
+        Action::ToggleBoundingBox,
+        "toggle_bounding_box",
+        ActionKind::Press,
+        "B",
+    ),
+    (Action::ToggleBloom, "toggle_bloom", ActionKind::Press, "L"),
+    (
+        Action::CycleToneMap,
+        "cycle_tone_map",
+        ActionKind::Press,
+        "M",
+    ),
+    (Action::ToggleSplit, "toggle_split", ActionKind::Press, "V"),
+    (Action::Undo, "undo", ActionKind::Press, "Ctrl+Z"),
+    (Action::Redo, "redo", ActionKind::Press, "Ctrl+Y"),
+    (
+        Action::SelectRegion,
+        "select_region",
+        ActionKind::Press,
+        "G",
+    ),
+    (Action::ClearRegion, "clear_region", ActionKind::Press, "C"),
+];
+
+const KEYS: [Key; 106] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Up,
+    Key::Apostrophe,
+    Key::Backquote,
+    Key::Backslash,
+    Key::Comma,
+    Key::Equal,
+    Key::LeftBracket,
+    Key::Minus,
+    Key::Period,
+    Key::RightBracket,
+    Key::Semicolon,
+    Key::Slash,
+    Key::Backspace,
+    Key::Delete,
+    Key::End,
+    Key::Enter,
+    Key::Escape,
+    Key::Home,
+    Key::Insert,
+    Key::Menu,
+    Key::PageDown,
+    Key::PageUp,
+    Key::Pause,
+    Key::Space,
+    Key::Tab,
+    Key::NumLock,
+    Key::CapsLock,
+    Key::ScrollLock,
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftCtrl,
+    Key::RightCtrl,
+    Key::NumPad0,
+    Key::NumPad1,
+    Key::NumPad2,
+    Key::NumPad3,
+    Key::NumPad4,
+    Key::NumPad5,
+    Key::NumPad6,
+    Key::NumPad7,
+    Key::NumPad8,
+    Key::NumPad9,
+    Key::NumPadDot,
+    Key::NumPadSlash,
+    Key::NumPadAsterisk,
+    Key::NumPadMinus,
+    Key::NumPadPlus,
+    Key::NumPadEnter,
+    Key::LeftAlt,
+    Key::RightAlt,
+    Key::LeftSuper,
+    Key::RightSuper,
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(Key),
+    // Modifier and key.
+    Chord(Key, Key),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn parse_key(name: &str) -> Option<Key> {
+        match name.to_ascii_lowercase().as_str() {
+            "ctrl" => Some(Key::LeftCtrl),
+            "shift" => Some(Key::LeftShift),
+            "alt" => Some(Key::LeftAlt),
+            "super" => Some(Key::LeftSuper),
+            _ => KEYS
+                .iter()
+                .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+                .copied(),
+        }
+    }
+
+    // Key names are the same as the minifb key names, ex. "W",
+    // "Key1" or "NumPadPlus". Chords are written as modifier+key, ex.
+    // "Ctrl+Z". Mouse buttons are "MouseLeft", "MouseMiddle" and
+    // "MouseRight".
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            _ => match name.split_once('+') {
+                Some((modifier, key)) => Binding::parse_key(modifier)
+                    .zip(Binding::parse_key(key))
+                    .map(|(modifier, key)| Binding::Chord(modifier, key)),
+                None => Binding::parse_key(name).map(Binding::Key),
+            },
+        }
+    }
+
+    fn kind_matches(&self, kind: ActionKind) -> bool {
+        match self {
+            Binding::Key(_) => kind != ActionKind::Drag,
+            Binding::Chord(_, _) => kind == ActionKind::Press,
+            Binding::Mouse(_) => kind == ActionKind::Drag,
+        }
+    }
+}
+
+// Maps keys and mouse buttons to actions. Starts out with the
+// default layout and the controls section in the config can rebind
+// any action. Binding an action to "None" disables it.
+#[derive(Clone)]
+pub struct Controls {
+    bindings: Vec<(Action, ActionKind, Binding)>,
+}
+
+impl Controls {
+    pub fn action(&self, binding: &Binding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, _, b)| b == binding)
+            .map(|(action, _, _)| *action)
+    }
+
+    // Action triggered by a released key or chord.
+    pub fn pressed(&self, event: &KeyEvent) -> Option<Action> {
+        match event {
+            KeyEvent::Released(key) => self.action(&Binding::Key(*key)),
+            KeyEvent::Chord(modifier, key) => self.action(&Binding::Chord(*modifier, *key)),
+            _ => None,
+        }
+    }
+
+    pub fn listen_events(&self) -> Vec<ListenKeyEvents> {
+        let keys = |kind: ActionKind| {
+            self.bindings
+                .iter()
+                .filter(|(_, k, _)| *k == kind)
+                .filter_map(|(_, _, binding)| match binding {
+                    Binding::Key(key) => Some(*key),
+                    _ => None,
+                })
+                .collect::<Vec<Key>>()
+        };
+        let chords = self
+            .bindings
+            .iter()
+            .filter_map(|(_, _, binding)| match binding {
+                Binding::Chord(modifier, key) => Some((*modifier, *key)),
+                _ => None,
+            })
+            .collect::<Vec<(Key, Key)>>();
+
+        let mut events = vec![
+            ListenKeyEvents::Release(keys(ActionKind::Press)),
+            ListenKeyEvents::Down(keys(ActionKind::Hold)),
+            ListenKeyEvents::Chord(chords),
+            ListenKeyEvents::Scroll,
+        ];
+        self.bindings
+            .iter()
+            .filter_map(|(_, _, binding)| match binding {
+                Binding::Mouse(button) => Some(ListenKeyEvents::MouseMove(*button)),
+                _ => None,
+            })
+            .for_each(|event| events.push(event));
+        events
+    }
+}
+
+impl TryFrom<&HashMap<String, String>> for Controls {
+    type Error = TracerError;
+
+    fn try_from(config: &HashMap<String, String>) -> Result<Self, Self::Error> {
+        if let Some(unknown) = config
+            .keys()
+            .find(|name| !ACTIONS.iter().any(|(_, n, _, _)| n == name))
+        {
+            return Err(TracerError::InvalidControls(format!(
+                "Unknown action \"{}\". Available actions are: {}",
+                unknown,
+                ACTIONS
+                    .iter()
+                    .map(|(_, n, _, _)| *n)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )));
+        }
+
+        let mut bindings: Vec<(Action, ActionKind, Binding)> = Vec::new();
+        ACTIONS
+            .iter()
+            .try_for_each(|(action, name, kind, default)| {
+                let binding_name = config.get(*name).map(String::as_str).unwrap_or(default);
+                if binding_name == "None" {
+                    return Ok(());
+                }
+
+                let binding = Binding::parse(binding_name)
+                    .filter(|binding| binding.kind_matches(*kind))
+                    .ok_or_else(|| {
+                        TracerError::InvalidControls(format!(
+                            "\"{}\" can not be bound to \"{}\". {}",
+                            name,
+                            binding_name,
+                            match kind {
+                                ActionKind::Drag => "Expected a mouse button.",
+                                _ => "Expected a key.",
+                            }
+                        ))
+                    })?;
+
+                match bindings.iter().find(|(_, _, b)| *b == binding) {
+                    Some((other, _, _)) => Err(TracerError::InvalidControls(format!(
+                        "\"{}\" is bound to both \"{}\" and \"{}\".",
+                        binding_name,
+                        ACTIONS
+                            .iter()
+                            .find(|(a, _, _, _)| a == other)
+                            .map(|(_, n, _, _)| *n)
+                            .unwrap_or_default(),
+                        name
+                    ))),
+                    None => {
+                        bindings.push((*action, *kind, binding));
+                        Ok(())
+                    }
+                }
+            })?;
+
+        Ok(Self { bindings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout() {
+        let controls = Controls::try_from(&HashMap::new()).unwrap();
+        assert_eq!(
+            controls.action(&Binding::Key(Key::W)),
+            Some(Action::MoveForward)
+        );
+        assert_eq!(
+            controls.action(&Binding::Mouse(MouseButton::Left)),
+            Some(Action::Look)
+        );
+        assert_eq!(controls.action(&Binding::Key(Key::Z)), None);
+        assert_eq!(
+            controls.action(&Binding::Chord(Key::LeftCtrl, Key::Z)),
+            Some(Action::Undo)
+        );
+    }
+
+    #[test]
+    fn rebind() {
+        let config = HashMap::from([
+            (String::from("fov_up"), String::from("Key1")),
+            (String::from("fov_down"), String::from("key2")),
+            (String::from("delete"), String::from("None")),
+        ]);
+        let controls = Controls::try_from(&config).unwrap();
+        assert_eq!(
+            controls.action(&Binding::Key(Key::Key1)),
+            Some(Action::FovUp)
+        );
+        assert_eq!(
+            controls.action(&Binding::Key(Key::Key2)),
+            Some(Action::FovDown)
+        );
+        assert_eq!(controls.action(&Binding::Key(Key::NumPadMinus)), None);
+        assert_eq!(controls.action(&Binding::Key(Key::E)), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let unknown_action = HashMap::from([(String::from("jump"), String::from("Space"))]);
+        assert!(Controls::try_from(&unknown_action).is_err());
+
+        let unknown_key = HashMap::from([(String::from("select"), String::from("Hyper"))]);
+        assert!(Controls::try_from(&unknown_key).is_err());
+
+        let duplicate = HashMap::from([(String::from("select"), String::from("W"))]);
+        assert!(Controls::try_from(&duplicate).is_err());
+
+        let wrong_kind = HashMap::from([(String::from("look"), String::from("W"))]);
+        assert!(Controls::try_from(&wrong_kind).is_err());
+
+        let held_chord = HashMap::from([(String::from("move_forward"), String::from("Ctrl+W"))]);
+        assert!(Controls::try_from(&held_chord).is_err());
+    }
+}
@@ -66,6 +66,27 @@ pub enum TracerError {
 
     #[error("Failed to parse \"{0}\" into a vector: {1}")]
     FailedToParse(String, String),
+
+    #[error("Invalid shutter: {0}")]
+    InvalidShutter(String),
+
+    #[error("Invalid controls: {0}")]
+    InvalidControls(String),
+
+    #[error("Distributed rendering failed: {0}")]
+    Distributed(String),
+
+    #[error("Control API failed: {0}")]
+    Api(String),
+
+    #[error("Post process failed: {0}")]
+    PostProcess(String),
+
+    #[error("Compare failed: {0}")]
+    Compare(String),
+
+    #[error("Invalid aperture: {0}")]
+    InvalidAperture(String),
 }
 
 impl From<TracerError> for i32 {
@@ -93,6 +114,13 @@ impl From<TracerError> for i32 {
             TracerError::NoObjectWithId(_) => 20,
             TracerError::FailedToOpenImage(_, _) => 21,
             TracerError::FailedToParse(_, _) => 22,
+            TracerError::InvalidShutter(_) => 23,
+            TracerError::InvalidControls(_) => 24,
+            TracerError::Distributed(_) => 25,
+            TracerError::Api(_) => 26,
+            TracerError::PostProcess(_) => 27,
+            TracerError::Compare(_) => 28,
+            TracerError::InvalidAperture(_) => 29,
         }
     }
 }
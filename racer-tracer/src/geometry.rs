@@ -1,5 +1,5 @@
+pub mod animated;
 pub mod r#box;
-pub mod moving_sphere;
 pub mod rotate_y;
 pub mod sphere;
 pub mod translate;
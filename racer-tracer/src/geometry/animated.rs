@@ -0,0 +1,266 @@
+use crate::{
+    aabb::Aabb,
+    ray::Ray,
+    scene::{HittableSceneObject, SceneObject},
+    util::degrees_to_radians,
+    vec3::Vec3,
+};
+
+use super::{HitRecord, Hittable};
+
+// Number of steps each keyframe segment is split into when
+// computing the bounding box. Rotations sweep arcs between the
+// sampled steps so the box is padded to cover those as well.
+const BOUNDING_BOX_STEPS: usize = 16;
+
+#[derive(Clone)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translate: Vec3,
+    // Euler angles in degrees applied in x, y, z order.
+    pub rotate: Vec3,
+}
+
+// Row major rotation matrix.
+struct Rotation {
+    rows: [Vec3; 3],
+}
+
+impl Rotation {
+    fn from_euler(degrees: &Vec3) -> Self {
+        let (sx, cx) = degrees_to_radians(*degrees.x()).sin_cos();
+        let (sy, cy) = degrees_to_radians(*degrees.y()).sin_cos();
+        let (sz, cz) = degrees_to_radians(*degrees.z()).sin_cos();
+
+        // Rz * Ry * Rx
+        Self {
+            rows: [
+                Vec3::new(cz * cy, cz * sy * sx - sz * cx, cz * sy * cx + sz * sx),
+                Vec3::new(sz * cy, sz * sy * sx + cz * cx, sz * sy * cx - cz * sx),
+                Vec3::new(-sy, cy * sx, cy * cx),
+            ],
+        }
+    }
+
+    fn apply(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.rows[0].dot(v),
+            self.rows[1].dot(v),
+            self.rows[2].dot(v),
+        )
+    }
+
+    // The inverse of a rotation is its transpose.
+    fn apply_inverse(&self, v: &Vec3) -> Vec3 {
+        self.rows[0] * v[0] + self.rows[1] * v[1] + self.rows[2] * v[2]
+    }
+}
+
+// Moves and rotates an object over time. The rotation is done around
+// the center of the object and the transform is evaluated at the
+// time of each ray. Times outside of the keyframes are clamped to
+// the first or last keyframe.
+#[derive(Clone)]
+pub struct Animated {
+    object: SceneObject,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animated {
+    pub fn new(object: SceneObject, mut keyframes: Vec<Keyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { object, keyframes }
+    }
+
+    fn transform(&self, time: f64) -> (Vec3, Vec3) {
+        let first = self.keyframes.first();
+        let last = self.keyframes.last();
+        match (first, last) {
+            (Some(first), _) if time <= first.time => (first.translate, first.rotate),
+            (_, Some(last)) if time >= last.time => (last.translate, last.rotate),
+            (Some(_), Some(_)) => self
+                .keyframes
+                .windows(2)
+                .find(|pair| time >= pair[0].time && time <= pair[1].time)
+                .map(|pair| {
+                    let span = pair[1].time - pair[0].time;
+                    let t = if span > 0.0 {
+                        (time - pair[0].time) / span
+                    } else {
+                        0.0
+                    };
+                    (
+                        pair[0].translate + t * (pair[1].translate - pair[0].translate),
+                        pair[0].rotate + t * (pair[1].rotate - pair[0].rotate),
+                    )
+                })
+                .unwrap_or_default(),
+            _ => (Vec3::default(), Vec3::default()),
+        }
+    }
+
+    fn pivot(&self) -> Vec3 {
+        let aabb = self.object.aabb();
+        (aabb.min() + *aabb.max()) / 2.0
+    }
+
+    fn sample_times(&self, time_a: f64, time_b: f64) -> Vec<f64> {
+        let mut times = vec![time_a, time_b];
+        self.keyframes.windows(2).for_each(|pair| {
+            (0..=BOUNDING_BOX_STEPS).for_each(|step| {
+                let t = pair[0].time
+                    + (pair[1].time - pair[0].time) * step as f64 / BOUNDING_BOX_STEPS as f64;
+                if t > time_a && t < time_b {
+                    times.push(t);
+                }
+            })
+        });
+        times
+    }
+
+    // Largest rotation in radians done by any axis between two
+    // consecutive bounding box samples.
+    fn max_rotation_step(&self) -> f64 {
+        self.keyframes
+            .windows(2)
+            .map(|pair| {
+                let delta = pair[1].rotate - pair[0].rotate;
+                degrees_to_radians(delta.x().abs().max(delta.y().abs()).max(delta.z().abs()))
+                    / BOUNDING_BOX_STEPS as f64
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+impl HittableSceneObject for Animated {
+    fn obj_hit(
+        &self,
+        _obj: &SceneObject,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        obj_id: usize,
+    ) -> Option<HitRecord> {
+        let (translate, rotate) = self.transform(ray.time());
+        let rotation = Rotation::from_euler(&rotate);
+        let pivot = self.pivot();
+
+        let local = Ray::new(
+            rotation.apply_inverse(&(ray.origin() - translate - pivot)) + pivot,
+            rotation.apply_inverse(ray.direction()),
+            ray.time(),
+        );
+
+        // Rigid transforms keep t and the side the normal is facing.
+        let mut record = self.object.hit(&local, t_min, t_max)?;
+        record.point = rotation.apply(&(record.point - pivot)) + pivot + translate;
+        record.normal = rotation.apply(&record.normal);
+        record.obj_id = obj_id;
+        Some(record)
+    }
+
+    fn create_bounding_box(&self, _pos: &Vec3, time_a: f64, time_b: f64) -> Aabb {
+        let aabb = self.object.aabb();
+        let pivot = self.pivot();
+        let mut min = Vec3::new(f64::MAX, f64::MAX, f64::MAX);
+        let mut max = Vec3::new(f64::MIN, f64::MIN, f64::MIN);
+
+        self.sample_times(time_a, time_b)
+            .into_iter()
+            .for_each(|time| {
+                let (translate, rotate) = self.transform(time);
+                let rotation = Rotation::from_euler(&rotate);
+                for i in 0..2 {
+                    for j in 0..2 {
+                        for k in 0..2 {
+                            let x = i as f64 * aabb.max().x() + ((1 - i) as f64) * aabb.min().x();
+                            let y = j as f64 * aabb.max().y() + ((1 - j) as f64) * aabb.min().y();
+                            let z = k as f64 * aabb.max().z() + ((1 - k) as f64) * aabb.min().z();
+                            let corner = Vec3::new(x, y, z);
+                            let moved = rotation.apply(&(corner - pivot)) + pivot + translate;
+                            min.min(&moved);
+                            max.max(&moved);
+                        }
+                    }
+                }
+            });
+
+        // Corners travel along arcs between the samples. Pad with the
+        // largest distance an arc can bulge out from its chord.
+        let radius = (aabb.max() - *aabb.min()).length() / 2.0;
+        let padding = radius * (1.0 - (self.max_rotation_step() / 2.0).cos());
+        Aabb::new(min - padding, max + padding)
+    }
+
+    fn update_pos(&mut self, pos_delta: &Vec3) {
+        self.object.update_pos(pos_delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{geometry_creation::create_sphere, material::lambertian::Lambertian};
+
+    use super::*;
+
+    fn moving_sphere() -> Animated {
+        let material = Arc::new(Lambertian::new_with_color(Vec3::new(0.5, 0.5, 0.5)));
+        Animated::new(
+            create_sphere(material, Vec3::default(), 1.0),
+            vec![
+                Keyframe {
+                    time: 1.0,
+                    translate: Vec3::new(10.0, 0.0, 0.0),
+                    rotate: Vec3::new(0.0, 90.0, 0.0),
+                },
+                Keyframe {
+                    time: 0.0,
+                    translate: Vec3::default(),
+                    rotate: Vec3::default(),
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let animated = moving_sphere();
+        let (translate, rotate) = animated.transform(0.25);
+        assert_eq!(translate, Vec3::new(2.5, 0.0, 0.0));
+        assert_eq!(rotate, Vec3::new(0.0, 22.5, 0.0));
+
+        // Clamped to the first and last keyframe.
+        assert_eq!(animated.transform(-1.0).0, Vec3::default());
+        assert_eq!(animated.transform(2.0).0, Vec3::new(10.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn hits_at_the_time_of_the_ray() {
+        let animated = moving_sphere();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let hit = |x: f64, time: f64| {
+            let ray = Ray::new(Vec3::new(x, 5.0, 0.0), down, time);
+            animated
+                .obj_hit(&animated.object, &ray, 0.001, f64::MAX, 1)
+                .map(|record| record.point)
+        };
+        assert!(hit(0.0, 0.0).is_some());
+        assert!(hit(0.0, 1.0).is_none());
+        let point = hit(5.0, 0.5).unwrap();
+        assert!((point - Vec3::new(5.0, 1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn bounding_box_covers_the_shutter_interval() {
+        let animated = moving_sphere();
+        let aabb = animated.create_bounding_box(&Vec3::default(), 0.0, 1.0);
+        assert!(*aabb.min().x() <= -1.0 && *aabb.max().x() >= 11.0);
+        assert!(*aabb.min().y() <= -1.0 && *aabb.max().y() >= 1.0);
+
+        // Only the part of the motion inside the interval.
+        let aabb = animated.create_bounding_box(&Vec3::default(), 0.0, 0.5);
+        assert!(*aabb.max().x() >= 6.0 && *aabb.max().x() < 8.0);
+    }
+}
@@ -85,7 +85,7 @@ impl HittableSceneObject for Boxx {
         ray: &crate::ray::Ray,
         t_min: f64,
         t_max: f64,
-        _obj_id: usize,
+        obj_id: usize,
     ) -> Option<super::HitRecord> {
         let mut rec = None;
         let mut closes_so_far = t_max;
@@ -97,7 +97,11 @@ impl HittableSceneObject for Boxx {
             }
         }
 
-        rec
+        // Report the box rather than the side that was hit.
+        rec.map(|mut rec| {
+            rec.obj_id = obj_id;
+            rec
+        })
     }
 
     fn create_bounding_box(
@@ -34,7 +34,7 @@ impl HittableSceneObject for RotateY {
         ray: &Ray,
         t_min: f64,
         t_max: f64,
-        _obj_id: usize,
+        obj_id: usize,
     ) -> Option<HitRecord> {
         let mut origin = *ray.origin();
         let mut direction = *ray.direction();
@@ -60,6 +60,7 @@ impl HittableSceneObject for RotateY {
 
         record.point = point;
         record.set_face_normal(&rotated, normal);
+        record.obj_id = obj_id;
         Some(record)
     }
 
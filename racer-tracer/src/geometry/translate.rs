@@ -27,13 +27,14 @@ impl HittableSceneObject for Translate {
         ray: &crate::ray::Ray,
         t_min: f64,
         t_max: f64,
-        _obj_id: usize,
+        obj_id: usize,
     ) -> Option<HitRecord> {
         let moved = Ray::new(ray.origin() - self.offset, *ray.direction(), ray.time());
         match self.object.hit(&moved, t_min, t_max) {
             Some(mut record) => {
                 record.point += self.offset;
                 record.set_face_normal(&moved, record.normal);
+                record.obj_id = obj_id;
                 Some(record)
             }
             None => None,
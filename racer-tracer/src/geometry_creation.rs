@@ -2,8 +2,14 @@ use std::sync::Arc;
 
 use crate::{
     geometry::{
-        moving_sphere::MovingSphere, r#box::Boxx, rotate_y::RotateY, sphere::Sphere,
-        translate::Translate, xy_rect::XyRect, xz_rect::XzRect, yz_rect::YzRect,
+        animated::{Animated, Keyframe},
+        r#box::Boxx,
+        rotate_y::RotateY,
+        sphere::Sphere,
+        translate::Translate,
+        xy_rect::XyRect,
+        xz_rect::XzRect,
+        yz_rect::YzRect,
     },
     material::Material,
     scene::{HittableSceneObject, SceneObject},
@@ -20,6 +26,7 @@ pub fn create_sphere(material: Arc<dyn Material>, pos: Vec3, radius: f64) -> Sce
     )
 }
 
+// A sphere moving from pos_a to pos_b between time_a and time_b.
 pub fn create_movable_sphere(
     material: Arc<dyn Material>,
     pos_a: Vec3,
@@ -28,12 +35,20 @@ pub fn create_movable_sphere(
     time_a: f64,
     time_b: f64,
 ) -> SceneObject {
-    let moving_sphere = MovingSphere::new(pos_b, radius, time_a, time_b);
-    SceneObject::new(
-        moving_sphere.create_bounding_box(&pos_a, 0.0, 0.0),
-        pos_a,
-        material,
-        Box::new(moving_sphere),
+    create_animated(
+        vec![
+            Keyframe {
+                time: time_a,
+                translate: Vec3::default(),
+                rotate: Vec3::default(),
+            },
+            Keyframe {
+                time: time_b,
+                translate: pos_b - pos_a,
+                rotate: Vec3::default(),
+            },
+        ],
+        create_sphere(material, pos_a, radius),
     )
 }
 
@@ -124,3 +139,20 @@ pub fn create_rotate_y(rotate_deg: f64, obj: SceneObject) -> SceneObject {
         Box::new(rotate_y),
     )
 }
+
+pub fn create_animated(keyframes: Vec<Keyframe>, obj: SceneObject) -> SceneObject {
+    let pos = obj.pos();
+    let material = obj.material();
+    let time_a = keyframes.iter().map(|k| k.time).fold(f64::MAX, f64::min);
+    let time_b = keyframes.iter().map(|k| k.time).fold(f64::MIN, f64::max);
+    let animated = Animated::new(obj, keyframes);
+
+    // Covering every keyframe means the box covers any shutter
+    // interval since the transform is clamped outside of them.
+    SceneObject::new(
+        animated.create_bounding_box(&pos, time_a.min(time_b), time_b.max(time_a)),
+        pos,
+        material,
+        Box::new(animated),
+    )
+}
@@ -0,0 +1,202 @@
+use crate::{camera::Camera, image::Region, scene::SceneObject, vec3::Vec3};
+
+const HIGHLIGHT_COLOR: u32 = 0xFF9900;
+const BOUNDING_BOX_COLOR: u32 = 0x00FFFF;
+const REGION_COLOR: u32 = 0xFFFF00;
+
+// How much of the highlight color is mixed into the selected object,
+// out of 256.
+const TINT: u32 = 64;
+
+// Edges are clipped at this distance in front of the camera so
+// corners behind it still give sensible lines.
+const NEAR_PLANE: f64 = 0.001;
+
+fn blend(a: u32, b: u32, amount: u32) -> u32 {
+    let channel = |shift: u32| {
+        let ca = (a >> shift) & 0xFF;
+        let cb = (b >> shift) & 0xFF;
+        ((ca * (256 - amount) + cb * amount) >> 8) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+// Clips the line to the screen. Returns None if no part of it is on
+// the screen.
+fn clip_line(
+    (x0, y0): (f64, f64),
+    (x1, y1): (f64, f64),
+    width: f64,
+    height: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+    let mut t0: f64 = 0.0;
+    let mut t1: f64 = 1.0;
+    for (p, q) in [
+        (-dx, x0),
+        (dx, width - 1.0 - x0),
+        (-dy, y0),
+        (dy, height - 1.0 - y0),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+
+    (t0 <= t1).then_some(((x0 + t0 * dx, y0 + t0 * dy), (x0 + t1 * dx, y0 + t1 * dy)))
+}
+
+fn draw_line(
+    from: (f64, f64),
+    to: (f64, f64),
+    color: u32,
+    buffer: &mut [u32],
+    width: usize,
+    height: usize,
+) {
+    if let Some(((x0, y0), (x1, y1))) = clip_line(from, to, width as f64, height as f64) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as usize;
+        (0..=steps).for_each(|step| {
+            let t = step as f64 / steps as f64;
+            let x = (x0 + t * (x1 - x0)).round() as usize;
+            let y = (y0 + t * (y1 - y0)).round() as usize;
+            if x < width && y < height {
+                buffer[y * width + x] = color;
+            }
+        });
+    }
+}
+
+// Marks the selected object on top of the screen buffer. Its pixels
+// get tinted with an outline where they border anything else. The
+// bounding box, which is what gets moved around, can be drawn as a
+// wireframe as well.
+pub struct Highlight {
+    bounding_box: bool,
+}
+
+impl Highlight {
+    pub fn new() -> Self {
+        Self {
+            bounding_box: false,
+        }
+    }
+
+    pub fn toggle_bounding_box(&mut self) {
+        self.bounding_box = !self.bounding_box;
+    }
+
+    pub fn draw(
+        &self,
+        camera: &Camera,
+        object: &SceneObject,
+        obj_id: &[usize],
+        buffer: &mut [u32],
+        width: usize,
+        height: usize,
+    ) {
+        let id = object.id();
+        let selected = |x: usize, y: usize| obj_id[y * width + x] == id;
+        for y in 0..height {
+            for x in 0..width {
+                if !selected(x, y) {
+                    continue;
+                }
+
+                let edge = x == 0
+                    || y == 0
+                    || x == width - 1
+                    || y == height - 1
+                    || !selected(x - 1, y)
+                    || !selected(x + 1, y)
+                    || !selected(x, y - 1)
+                    || !selected(x, y + 1);
+                let pixel = &mut buffer[y * width + x];
+                *pixel = if edge {
+                    HIGHLIGHT_COLOR
+                } else {
+                    blend(*pixel, HIGHLIGHT_COLOR, TINT)
+                };
+            }
+        }
+
+        if self.bounding_box {
+            self.draw_bounding_box(camera, object, buffer, width, height);
+        }
+    }
+
+    // Outlines the part of the image final renders are limited to.
+    pub fn draw_region(&self, region: &Region, buffer: &mut [u32], width: usize, height: usize) {
+        let left = region.x as f64;
+        let top = region.y as f64;
+        let right = (region.x + region.width - 1) as f64;
+        let bottom = (region.y + region.height - 1) as f64;
+        [
+            ((left, top), (right, top)),
+            ((right, top), (right, bottom)),
+            ((right, bottom), (left, bottom)),
+            ((left, bottom), (left, top)),
+        ]
+        .into_iter()
+        .for_each(|(from, to)| draw_line(from, to, REGION_COLOR, buffer, width, height));
+    }
+
+    fn draw_bounding_box(
+        &self,
+        camera: &Camera,
+        object: &SceneObject,
+        buffer: &mut [u32],
+        width: usize,
+        height: usize,
+    ) {
+        let aabb = object.aabb();
+        let bounds = [aabb.min(), aabb.max()];
+        let corner = |i: usize| {
+            Vec3::new(
+                *bounds[i & 1].x(),
+                *bounds[(i >> 1) & 1].y(),
+                *bounds[(i >> 2) & 1].z(),
+            )
+        };
+
+        // Every pair of corners that differ in exactly one axis.
+        (0..8)
+            .flat_map(|a| [1, 2, 4].into_iter().map(move |axis| (a, a | axis)))
+            .filter(|(a, b)| a != b)
+            .for_each(|(a, b)| {
+                let mut start = corner(a);
+                let mut end = corner(b);
+                let start_distance = camera.view_distance(&start);
+                let end_distance = camera.view_distance(&end);
+                if start_distance < NEAR_PLANE && end_distance < NEAR_PLANE {
+                    return;
+                } else if start_distance < NEAR_PLANE {
+                    let t = (NEAR_PLANE - start_distance) / (end_distance - start_distance);
+                    start = start + t * (end - start);
+                } else if end_distance < NEAR_PLANE {
+                    let t = (NEAR_PLANE - end_distance) / (start_distance - end_distance);
+                    end = end + t * (start - end);
+                }
+
+                if let (Some((u0, v0)), Some((u1, v1))) =
+                    (camera.project(&start), camera.project(&end))
+                {
+                    draw_line(
+                        (u0 * width as f64, v0 * height as f64),
+                        (u1 * width as f64, v1 * height as f64),
+                        BOUNDING_BOX_COLOR,
+                        buffer,
+                        width,
+                        height,
+                    );
+                }
+            });
+    }
+}
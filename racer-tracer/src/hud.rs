@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use crate::{camera::Camera, renderer::ProgressInfo, scene::Scene, vec3::Vec3};
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const SCALE: usize = 2;
+const CHAR_ADVANCE: usize = (GLYPH_WIDTH + 1) * SCALE;
+const LINE_ADVANCE: usize = (GLYPH_HEIGHT + 3) * SCALE;
+const MARGIN: usize = 8;
+const TEXT_COLOR: u32 = 0xFFFFFF;
+
+// How much of the previous fps value is kept each frame.
+const FPS_SMOOTHING: f64 = 0.95;
+
+// 5x7 bitmap font. Each row is 5 bits with the leftmost pixel in the
+// highest bit. Lower case letters are drawn as upper case.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+fn format_vec(v: &Vec3) -> String {
+    format!("({:.1}, {:.1}, {:.1})", v.x(), v.y(), v.z())
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+// Text overlay drawn on top of the screen buffer with information
+// about the camera, the render and the selected object.
+pub struct Hud {
+    visible: bool,
+    fps: f64,
+}
+
+impl Hud {
+    pub fn new(visible: bool) -> Self {
+        Self { visible, fps: 0.0 }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    // dt in microseconds.
+    pub fn update_fps(&mut self, dt: f64) {
+        if dt > 0.0 {
+            self.fps = FPS_SMOOTHING * self.fps + (1.0 - FPS_SMOOTHING) * 1_000_000.0 / dt;
+        }
+    }
+
+    pub fn lines(&self, camera: &Camera, scene: &Scene, progress: &ProgressInfo) -> Vec<String> {
+        let mode = match (progress.final_render, progress.rendering) {
+            (true, true) => "Final (rendering)",
+            (true, false) => "Final (done)",
+            (false, _) => "Preview",
+        };
+        let selected = scene
+            .selected_object()
+            .and_then(|cookie| scene.get_pos(&cookie).ok().map(|pos| (cookie.id, pos)))
+            .map_or_else(
+                || String::from("None"),
+                |(id, pos)| format!("#{} {}", id, format_vec(&pos)),
+            );
+
+        vec![
+            format!("FPS: {:.0}", self.fps),
+            format!("Mode: {}", mode),
+            format!(
+                "Samples: {:.1}/{} ({:.0}%)",
+                progress.samples_done(),
+                progress.samples,
+                progress.fraction() * 100.0
+            ),
+            format!(
+                "ETA: {}",
+                progress
+                    .eta()
+                    .map_or_else(|| String::from("-"), format_duration)
+            ),
+            format!("Camera: {}", format_vec(&camera.pos())),
+            format!(
+                "FOV: {:.1} Aperture: {:.2} Focus: {:.1}",
+                camera.get_vfov(),
+                camera.get_aperture(),
+                camera.get_focus_distance()
+            ),
+            format!("Selected: {}", selected),
+        ]
+    }
+
+    pub fn draw(&self, lines: &[String], buffer: &mut [u32], width: usize, height: usize) {
+        let panel_width = (lines.iter().map(|l| l.len()).max().unwrap_or(0) * CHAR_ADVANCE
+            + 2 * MARGIN)
+            .min(width);
+        let panel_height = (lines.len() * LINE_ADVANCE + 2 * MARGIN).min(height);
+
+        // Darken the area behind the text so it is readable on top of
+        // bright images.
+        for row in 0..panel_height {
+            for column in 0..panel_width {
+                let pixel = &mut buffer[row * width + column];
+                *pixel = (*pixel >> 2) & 0x3F3F3F;
+            }
+        }
+
+        lines.iter().enumerate().for_each(|(line_index, line)| {
+            let y = MARGIN + line_index * LINE_ADVANCE;
+            line.chars().enumerate().for_each(|(char_index, c)| {
+                let x = MARGIN + char_index * CHAR_ADVANCE;
+                glyph(c).iter().enumerate().for_each(|(glyph_row, bits)| {
+                    (0..GLYPH_WIDTH)
+                        .filter(|glyph_column| bits & (0x10 >> glyph_column) != 0)
+                        .for_each(|glyph_column| {
+                            for sy in 0..SCALE {
+                                for sx in 0..SCALE {
+                                    let px = x + glyph_column * SCALE + sx;
+                                    let py = y + glyph_row * SCALE + sy;
+                                    if px < width && py < height {
+                                        buffer[py * width + px] = TEXT_COLOR;
+                                    }
+                                }
+                            }
+                        })
+                })
+            })
+        });
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use crate::data_bus::DataWriter;
 
 #[derive(Clone)]
@@ -23,6 +25,36 @@ impl Image {
     }
 }
 
+// Part of the image in pixels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    // Region spanned by two corners in screen coordinates. Clamped to
+    // the image, None if nothing of it is left.
+    pub fn from_corners(image: &Image, a: (f64, f64), b: (f64, f64)) -> Option<Self> {
+        let clamp_x = |x: f64| (x.max(0.0) as usize).min(image.width);
+        let clamp_y = |y: f64| (y.max(0.0) as usize).min(image.height);
+        let (x0, x1) = (clamp_x(a.0.min(b.0)), clamp_x(a.0.max(b.0)));
+        let (y0, y1) = (clamp_y(a.1.min(b.1)), clamp_y(a.1.max(b.1)));
+        (x1 > x0 && y1 > y0).then_some(Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        })
+    }
+
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
+
 pub struct SubImage<T: Clone> {
     pub x: usize,
     pub y: usize,
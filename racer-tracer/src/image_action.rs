@@ -1,30 +1,111 @@
+pub mod command;
+pub mod exr;
 pub mod none;
+pub mod output_name;
 pub mod png;
 
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
 use slog::Logger;
 
-use crate::image_action::{none::None, png::SavePng};
+use crate::image_action::{command::RunCommand, exr::SaveExr, none::None, png::SavePng};
 
+use crate::renderer::report::RenderReport;
 use crate::vec3::Color;
 use crate::{
     config::{Config, ImageActionConfig},
     error::TracerError,
 };
 
+// Counts the finished images since the start, used for {frame}.
+static FRAME: AtomicUsize = AtomicUsize::new(1);
+
+// Copy of a finished render handed to the image actions.
+pub struct FinishedImage {
+    // Tone mapped, what is shown in the window.
+    pub rgb: Vec<Color>,
+    // Straight from the renderer.
+    pub linear: Vec<Color>,
+    pub report: Option<RenderReport>,
+    pub frame: usize,
+    pub time: SystemTime,
+}
+
+impl FinishedImage {
+    pub fn new(rgb: Vec<Color>, linear: Vec<Color>, report: Option<RenderReport>) -> Self {
+        Self {
+            rgb,
+            linear,
+            report,
+            frame: FRAME.fetch_add(1, Ordering::SeqCst),
+            time: SystemTime::now(),
+        }
+    }
+}
+
 pub trait ImageAction: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Returns the files written, the image first. Outputs has what
+    // the actions before this one wrote.
     fn action(
         &self,
-        screen_buffer: &[Color],
+        image: &FinishedImage,
+        outputs: &[PathBuf],
         config: &Config,
         log: &Logger,
-    ) -> Result<(), TracerError>;
+    ) -> Result<Vec<PathBuf>, TracerError>;
 }
 
-impl From<&ImageActionConfig> for &dyn ImageAction {
+impl From<&ImageActionConfig> for Box<dyn ImageAction> {
     fn from(image_action: &ImageActionConfig) -> Self {
         match image_action {
-            ImageActionConfig::None => &None {} as &dyn ImageAction,
-            ImageActionConfig::SavePng => &SavePng {} as &dyn ImageAction,
+            ImageActionConfig::None => Box::new(None {}),
+            ImageActionConfig::SavePng => Box::new(SavePng {}),
+            ImageActionConfig::SaveExr => Box::new(SaveExr {}),
+            ImageActionConfig::Command { command, args } => {
+                Box::new(RunCommand::new(command.clone(), args.clone()))
+            }
+        }
+    }
+}
+
+// Runs every action in order. One failing doesn't stop the ones after
+// it.
+pub struct ImageActions {
+    actions: Vec<Box<dyn ImageAction>>,
+}
+
+impl From<&[ImageActionConfig]> for ImageActions {
+    fn from(actions: &[ImageActionConfig]) -> Self {
+        Self {
+            actions: actions.iter().map(|action| action.into()).collect(),
+        }
+    }
+}
+
+impl ImageActions {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    // Returns how many of the actions failed.
+    pub fn run(&self, image: &FinishedImage, config: &Config, log: &Logger) -> usize {
+        let mut outputs = Vec::new();
+        let mut failed = 0;
+        for action in &self.actions {
+            match action.action(image, &outputs, config, log) {
+                Ok(written) => outputs.extend(written),
+                Err(e) => {
+                    error!(log, "Image action {} failed: {}", action.name(), e);
+                    failed += 1;
+                }
+            }
         }
+        failed
     }
 }
@@ -0,0 +1,67 @@
+use std::{path::PathBuf, process::Command};
+
+use slog::Logger;
+
+use crate::{config::Config, error::TracerError};
+
+use super::{FinishedImage, ImageAction};
+
+// Runs a user command once the image is done. The files written by
+// the actions before it are passed in environment variables.
+pub struct RunCommand {
+    command: String,
+    args: Vec<String>,
+}
+
+impl RunCommand {
+    pub fn new(command: String, args: Vec<String>) -> Self {
+        Self { command, args }
+    }
+}
+
+impl ImageAction for RunCommand {
+    fn name(&self) -> &str {
+        &self.command
+    }
+
+    fn action(
+        &self,
+        _image: &FinishedImage,
+        outputs: &[PathBuf],
+        _config: &Config,
+        log: &Logger,
+    ) -> Result<Vec<PathBuf>, TracerError> {
+        let error = |e: String| TracerError::ImageSave(format!("{}: {}", self.command, e));
+        let mut command = Command::new(&self.command);
+        command.args(&self.args);
+        if let Some(output) = outputs.first() {
+            command.env("RACER_TRACER_OUTPUT", output);
+        }
+        command.env(
+            "RACER_TRACER_OUTPUTS",
+            std::env::join_paths(outputs).map_err(|e| error(e.to_string()))?,
+        );
+
+        info!(log, "Running {}...", self.command);
+        let output = command.output().map_err(|e| error(e.to_string()))?;
+        if !output.stdout.is_empty() {
+            info!(
+                log,
+                "{}",
+                String::from_utf8_lossy(&output.stdout).trim_end()
+            );
+        }
+        if !output.stderr.is_empty() {
+            warn!(
+                log,
+                "{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+        output
+            .status
+            .success()
+            .then(Vec::new)
+            .ok_or_else(|| error(format!("Exited with {}", output.status)))
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+use slog::Logger;
+
+use crate::{config::Config, error::TracerError};
+
+use super::{output_name::output_path, FinishedImage, ImageAction};
+
+// Saves the image before tone mapping as 32 bit float.
+pub struct SaveExr {}
+
+impl ImageAction for SaveExr {
+    fn name(&self) -> &str {
+        "SaveExr"
+    }
+
+    fn action(
+        &self,
+        image: &FinishedImage,
+        _outputs: &[PathBuf],
+        config: &Config,
+        log: &Logger,
+    ) -> Result<Vec<PathBuf>, TracerError> {
+        match &config.image_output_dir {
+            Some(image_dir) => {
+                let data = image
+                    .linear
+                    .iter()
+                    .flat_map(|c| [*c.x() as f32, *c.y() as f32, *c.z() as f32])
+                    .collect::<Vec<f32>>();
+
+                info!(log, "Saving exr...");
+                let hash = format!(
+                    "{:X}",
+                    Sha256::digest(
+                        data.iter()
+                            .flat_map(|v| v.to_le_bytes())
+                            .collect::<Vec<u8>>()
+                    )
+                );
+                let file_path = output_path(image_dir, config, image, &hash, &["exr"])?;
+
+                img::Rgb32FImage::from_raw(
+                    config.screen.width as u32,
+                    config.screen.height as u32,
+                    data,
+                )
+                .ok_or_else(|| TracerError::ImageSave(String::from("Image has the wrong size")))
+                .and_then(|buffer| {
+                    buffer
+                        .save(&file_path)
+                        .map_err(|e| TracerError::ImageSave(e.to_string()))
+                })
+                .map(|_| {
+                    info!(log, "Saved exr to: {}", file_path.to_string_lossy());
+                    vec![file_path]
+                })
+            }
+            None => {
+                info!(log, "No output directory for saving exrs. Skipping.");
+                Ok(Vec::new())
+            }
+        }
+    }
+}
@@ -1,18 +1,25 @@
+use std::path::PathBuf;
+
 use slog::Logger;
 
-use crate::{config::Config, error::TracerError, vec3::Color};
+use crate::{config::Config, error::TracerError};
 
-use super::ImageAction;
+use super::{FinishedImage, ImageAction};
 
 pub struct None {}
 
 impl ImageAction for None {
+    fn name(&self) -> &str {
+        "None"
+    }
+
     fn action(
         &self,
-        _screen_buffer: &[Color],
+        _image: &FinishedImage,
+        _outputs: &[PathBuf],
         _config: &Config,
         _log: &Logger,
-    ) -> Result<(), TracerError> {
-        Ok(())
+    ) -> Result<Vec<PathBuf>, TracerError> {
+        Ok(Vec::new())
     }
 }
@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::{Config, SceneLoaderConfig},
+    error::TracerError,
+};
+
+use super::FinishedImage;
+
+// Values for the placeholders in the output_name template.
+pub struct NameValues<'a> {
+    pub scene: &'a str,
+    pub samples: u64,
+    pub tonemap: &'a str,
+    pub hash: &'a str,
+    pub frame: usize,
+    pub time: SystemTime,
+}
+
+// Year, month, day, hour, minute and second in UTC.
+fn civil_time(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let (days, rest) = (seconds.div_euclid(86400), seconds.rem_euclid(86400) as u32);
+
+    // Days to a date, from Howard Hinnant's date algorithms.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rest / 3600, (rest / 60) % 60, rest % 60)
+}
+
+// Fills in the template. The result is relative to the output
+// directory and has no extension.
+pub fn expand(template: &str, values: &NameValues) -> Result<String, TracerError> {
+    let (year, month, day, hour, minute, second) = civil_time(values.time);
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            TracerError::ImageSave(format!("Unclosed placeholder in output_name: {}", template))
+        })? + start;
+        let value = match &rest[start + 1..end] {
+            "scene" => values.scene.to_string(),
+            "date" => format!("{:04}-{:02}-{:02}", year, month, day),
+            "time" => format!("{:02}-{:02}-{:02}", hour, minute, second),
+            "frame" => format!("{:04}", values.frame),
+            "samples" => values.samples.to_string(),
+            "tonemap" => values.tonemap.to_string(),
+            "hash" => values.hash.to_string(),
+            placeholder => {
+                return Err(TracerError::ImageSave(format!(
+                    "Unknown placeholder {{{}}} in output_name",
+                    placeholder
+                )))
+            }
+        };
+        name.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+
+    if name.trim().is_empty() {
+        return Err(TracerError::ImageSave(format!(
+            "output_name {} gives an empty name",
+            template
+        )));
+    }
+    // Images are kept inside the output directory.
+    if !Path::new(&name)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(TracerError::ImageSave(format!(
+            "output_name {} gives {} which is not inside the output directory",
+            template, name
+        )));
+    }
+    Ok(name)
+}
+
+// First path that doesn't exist yet with any of the extensions,
+// adding -1, -2... to the name when needed. The path has the first
+// extension, the others are for files written next to it.
+pub fn unique_path(dir: &Path, name: &str, extensions: &[&str]) -> PathBuf {
+    let free = |name: &str| {
+        extensions
+            .iter()
+            .all(|extension| !dir.join(format!("{}.{}", name, extension)).exists())
+    };
+    let name = if free(name) {
+        name.to_string()
+    } else {
+        (1..)
+            .map(|n| format!("{}-{}", name, n))
+            .find(|name| free(name))
+            .unwrap_or_else(|| name.to_string())
+    };
+    dir.join(format!("{}.{}", name, extensions[0]))
+}
+
+fn scene_name(loader: &SceneLoaderConfig) -> String {
+    match loader {
+        SceneLoaderConfig::Yml { path } => path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("scene")),
+        SceneLoaderConfig::Random => String::from("random"),
+        SceneLoaderConfig::Sandbox => String::from("sandbox"),
+        SceneLoaderConfig::None => String::from("none"),
+    }
+}
+
+// Where to save the image in the output directory. Creates the
+// directories in the name. See unique_path for the extensions.
+pub fn output_path(
+    dir: &Path,
+    config: &Config,
+    image: &FinishedImage,
+    hash: &str,
+    extensions: &[&str],
+) -> Result<PathBuf, TracerError> {
+    let report = image.report.as_ref();
+    let name = expand(
+        &config.output_name,
+        &NameValues {
+            scene: &scene_name(&config.loader),
+            samples: report.map_or(config.render.samples as u64, |r| r.samples()),
+            tonemap: report
+                .map_or(Some(&config.tone_map), |r| r.tone_map())
+                .map_or("None", |t| t.name()),
+            hash,
+            frame: image.frame,
+            time: image.time,
+        },
+    )?;
+    let path = unique_path(dir, &name, extensions);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| TracerError::ImageSave(format!("{}: {}", parent.display(), e)))?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn values(time: SystemTime) -> NameValues<'static> {
+        NameValues {
+            scene: "cornell_box",
+            samples: 200,
+            tonemap: "Aces",
+            hash: "ABC",
+            frame: 7,
+            time,
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        // 2023-03-14 15:09:26 UTC
+        let time = UNIX_EPOCH + Duration::from_secs(1678806566);
+        assert_eq!(
+            expand(
+                "{scene}/{date}_{time}_{frame}_{samples}spp_{tonemap}_{hash}",
+                &values(time)
+            )
+            .unwrap(),
+            "cornell_box/2023-03-14_15-09-26_0007_200spp_Aces_ABC"
+        );
+        assert_eq!(expand("{hash}", &values(UNIX_EPOCH)).unwrap(), "ABC");
+        assert_eq!(expand("{date}", &values(UNIX_EPOCH)).unwrap(), "1970-01-01");
+        assert!(expand("{nope}", &values(time)).is_err());
+        assert!(expand("{scene", &values(time)).is_err());
+        assert!(expand("../{hash}", &values(time)).is_err());
+        assert!(expand("/tmp/{hash}", &values(time)).is_err());
+        assert!(expand("a/../../{hash}", &values(time)).is_err());
+        assert!(expand("./{scene}/{hash}", &values(time)).is_ok());
+    }
+
+    #[test]
+    fn adds_suffix_on_collision() {
+        let dir = std::env::temp_dir().join(format!("racer-tracer-names-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = unique_path(&dir, "image", &["png"]);
+        assert_eq!(first, dir.join("image.png"));
+        std::fs::write(&first, []).unwrap();
+        assert_eq!(
+            unique_path(&dir, "image", &["png"]),
+            dir.join("image-1.png")
+        );
+        // A report left without its image is not overwritten either.
+        std::fs::write(dir.join("image-1.json"), []).unwrap();
+        assert_eq!(
+            unique_path(&dir, "image", &["png", "json"]),
+            dir.join("image-2.png")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,24 +1,77 @@
-use std::path::PathBuf;
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
 
 use sha2::{Digest, Sha256};
 use slog::Logger;
 
-use crate::{config::Config, error::TracerError, vec3::Color};
+use crate::{config::Config, error::TracerError};
 
-use super::ImageAction;
+use super::{output_name::output_path, FinishedImage, ImageAction};
 
 pub struct SavePng {}
 
+// tEXt chunks can only hold Latin-1. Anything else goes in an iTXt
+// chunk which is UTF-8.
+fn add_text<W: std::io::Write>(
+    encoder: &mut png::Encoder<W>,
+    keyword: &str,
+    text: String,
+) -> Result<(), png::EncodingError> {
+    if text.chars().all(|c| (c as u32) < 256) {
+        encoder.add_text_chunk(keyword.to_string(), text)
+    } else {
+        encoder.add_itxt_chunk(keyword.to_string(), text)
+    }
+}
+
+fn write_png(
+    path: &Path,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    report: Option<&str>,
+) -> Result<(), TracerError> {
+    let error = |e: png::EncodingError| TracerError::ImageSave(e.to_string());
+    let file = File::create(path).map_err(|e| TracerError::ImageSave(e.to_string()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    add_text(
+        &mut encoder,
+        "Software",
+        format!("racer-tracer {}", env!("CARGO_PKG_VERSION")),
+    )
+    .map_err(error)?;
+    if let Some(report) = report {
+        add_text(&mut encoder, "racer-tracer:report", report.to_string()).map_err(error)?;
+    }
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(data))
+        .map_err(error)
+}
+
 impl ImageAction for SavePng {
+    fn name(&self) -> &str {
+        "SavePng"
+    }
+
     fn action(
         &self,
-        screen_buffer: &[Color],
+        image: &FinishedImage,
+        _outputs: &[PathBuf],
         config: &Config,
         log: &Logger,
-    ) -> Result<(), TracerError> {
+    ) -> Result<Vec<PathBuf>, TracerError> {
         match &config.image_output_dir {
             Some(image_dir) => {
-                let png_data = screen_buffer
+                let png_data = image
+                    .rgb
                     .iter()
                     .map(|v| {
                         let red: u32 = (v[0] * 255.0) as u32;
@@ -35,27 +88,44 @@ impl ImageAction for SavePng {
 
                 sha.update(&png_data);
 
-                let mut file_path = PathBuf::from(image_dir);
-                file_path.push(format!("{:X}.png", sha.finalize()));
+                let file_path = output_path(
+                    image_dir,
+                    config,
+                    image,
+                    &format!("{:X}", sha.finalize()),
+                    &["png", "json"],
+                )?;
 
-                img::save_buffer(
+                let report = image.report.as_ref().map(|report| report.to_json());
+                write_png(
                     file_path.as_path(),
                     png_data.as_slice(),
                     config.screen.width as u32,
                     config.screen.height as u32,
-                    img::ColorType::Rgba8,
+                    report.as_deref(),
                 )
-                .map_err(|e| {
-                    let error = e.to_string();
-                    TracerError::ImageSave(error)
-                })
                 .map(|_| {
                     info!(log, "Saved image to: {}", file_path.to_string_lossy());
-                })
+                })?;
+
+                // The report is also written next to the image so it
+                // can be read without a png tool.
+                match report {
+                    Some(report) => {
+                        let report_path = file_path.with_extension("json");
+                        fs::write(&report_path, report)
+                            .map_err(|e| TracerError::ImageSave(e.to_string()))
+                            .map(|_| {
+                                info!(log, "Saved report to: {}", report_path.to_string_lossy());
+                                vec![file_path, report_path]
+                            })
+                    }
+                    None => Ok(vec![file_path]),
+                }
             }
             None => {
                 info!(log, "No output directory for saving pngs. Skipping.");
-                Ok(())
+                Ok(Vec::new())
             }
         }
     }
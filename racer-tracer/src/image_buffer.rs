@@ -1,18 +1,25 @@
+use std::time::{Duration, Instant};
+
 use crate::{
+    compare::{compare, false_color},
+    config::ToneMapConfig,
     data_bus::{DataBus, DataReader, DataWriter},
     error::TracerError,
     image::Image,
+    post_process::{output::srgb_to_linear, PostProcessPipeline},
+    renderer::image::ImageDisplayer,
     tone_map::ToneMap,
     vec3::Color,
 };
 
 // There should be more buffers eventually. Will probably need
-// z-buffer, pos, normal, object_id and albedo later.
+// z-buffer, pos, normal and albedo later.
 pub struct ImageBufferReader {
     reader: DataReader<ImageBufferEvent>,
     changed: bool,
     image_width: usize,
     rgb: Vec<Color>,
+    obj_id: Vec<usize>,
 }
 
 impl ImageBufferReader {
@@ -20,6 +27,7 @@ impl ImageBufferReader {
         Self {
             image_width: image.width,
             rgb: vec![Color::default(); image.width * image.height],
+            obj_id: vec![0; image.width * image.height],
             reader,
             changed: false,
         }
@@ -44,6 +52,21 @@ impl ImageBufferReader {
                         }
                     }
                 }
+                ImageBufferEvent::ObjectIdUpdate {
+                    obj_id,
+                    r,
+                    c,
+                    width,
+                    height,
+                } => {
+                    for row in 0..height {
+                        for column in 0..width {
+                            let buffer_index = row * width + column;
+                            let index = (r + row) * self.image_width + c + column;
+                            self.obj_id[index] = obj_id[buffer_index];
+                        }
+                    }
+                }
             })
         })
     }
@@ -57,6 +80,12 @@ impl ImageBufferReader {
     pub fn rgb(&self) -> &[Color] {
         &self.rgb
     }
+
+    // Id of the object first hit by each pixel. 0 when nothing was
+    // hit.
+    pub fn obj_id(&self) -> &[usize] {
+        &self.obj_id
+    }
 }
 
 #[derive(Clone)]
@@ -68,6 +97,13 @@ pub enum ImageBufferEvent {
         width: usize,
         height: usize,
     },
+    ObjectIdUpdate {
+        obj_id: Vec<usize>,
+        r: usize,
+        c: usize,
+        width: usize,
+        height: usize,
+    },
 }
 
 pub struct ImageBuffer {
@@ -101,34 +137,95 @@ impl ImageBuffer {
     }
 }
 
+// How often the whole image is post processed again while parts of it
+// come in. Only done when there are stages that need the whole image.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+// What the right side of the split view shows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Split {
+    Off,
+    // The image with the next tone map.
+    ToneMap,
+    Reference,
+    // The perceptual error against the reference.
+    Difference,
+}
+
+impl Split {
+    pub fn next(self, has_reference: bool) -> Self {
+        match self {
+            Split::Off => Split::ToneMap,
+            Split::ToneMap if has_reference => Split::Reference,
+            Split::Reference => Split::Difference,
+            _ => Split::Off,
+        }
+    }
+}
+
+// Changes to what is shown, sent from the window.
+pub enum DisplayCommand {
+    ToggleEffects,
+    CycleToneMap,
+    Split(Split),
+    // Column of the split view divider.
+    Divider(usize),
+}
+
+// What can be switched between while running.
+pub struct DisplayOptions {
+    pub tone_maps: Vec<ToneMapConfig>,
+    // Index of the tone map in use.
+    pub tone_map: usize,
+    pub reference: Option<ImageDisplayer>,
+}
+
 // The point of this is to combine all sources to the finished image source
 pub struct ScreenBuffer {
     buffer: Vec<Color>,
+    // The image before post processing.
+    linear: Vec<Color>,
     out: DataWriter<ImageBufferEvent>,
     reader: DataReader<ImageBufferEvent>,
     bus: DataBus<ImageBufferEvent>,
     image: Image,
-
-    // TODO: Have something more generic that could apply many
-    // different pp effects. A list of something.
-    // It's fine for now since it's the only thing we support so far.
-    tone_map: Box<dyn ToneMap>,
+    post_process: PostProcessPipeline,
+    // Parts of the image changed since the whole image was processed.
+    dirty: bool,
+    refreshed: Instant,
+    options: DisplayOptions,
+    split: Split,
+    split_tone_map: Box<dyn ToneMap>,
+    divider: usize,
+    // Error of each pixel against the reference.
+    difference: Vec<f64>,
 }
 
 impl ScreenBuffer {
     pub fn new(
         image: Image,
         out: DataWriter<ImageBufferEvent>,
-        tone_map: Box<dyn ToneMap>,
+        post_process: PostProcessPipeline,
+        options: DisplayOptions,
     ) -> Self {
         let mut bus = DataBus::<ImageBufferEvent>::new("ScreenBuffer");
+        let split_tone_map =
+            (&options.tone_maps[(options.tone_map + 1) % options.tone_maps.len()]).into();
         Self {
             buffer: vec![Color::default(); image.height * image.width],
+            linear: vec![Color::default(); image.height * image.width],
+            divider: image.width / 2,
             out,
             image,
             reader: bus.get_reader(),
             bus,
-            tone_map,
+            post_process,
+            dirty: false,
+            refreshed: Instant::now(),
+            options,
+            split: Split::Off,
+            split_tone_map,
+            difference: Vec::new(),
         }
     }
 
@@ -147,12 +244,16 @@ impl ScreenBuffer {
                             for row in 0..height {
                                 for column in 0..width {
                                     let buffer_index = row * width + column;
-                                    rgb[buffer_index] = self.tone_map.tone_map(&rgb[buffer_index]);
-                                    self.buffer[(r + row) * self.image.width + c + column] =
-                                        rgb[buffer_index]
+                                    let index = (r + row) * self.image.width + c + column;
+                                    self.linear[index] = rgb[buffer_index];
+                                    self.buffer[index] =
+                                        self.post_process.process(index, &rgb[buffer_index]);
+                                    rgb[buffer_index] = self.shown(index, &self.buffer[index]);
                                 }
                             }
 
+                            self.dirty = self.post_process.has_image_stages();
+
                             // Data processed.
                             // Pass it to the readers.
                             self.out.write(ImageBufferEvent::BufferUpdate {
@@ -163,12 +264,168 @@ impl ScreenBuffer {
                                 height,
                             })
                         }
+                        // Nothing to process, just pass it on.
+                        event @ ImageBufferEvent::ObjectIdUpdate { .. } => self.out.write(event),
                     }
                 })
             })
+        })?;
+
+        if self.dirty && self.refreshed.elapsed() >= REFRESH_INTERVAL {
+            self.refresh(false)?;
+        }
+        Ok(())
+    }
+
+    // Post processes the whole image again from the linear buffer.
+    fn refresh(&mut self, settle: bool) -> Result<(), TracerError> {
+        let (buffer, settled) = self
+            .post_process
+            .process_image(&self.image, &self.linear, settle);
+        self.buffer = buffer;
+        // Keeps refreshing until stages like auto exposure are done
+        // adapting.
+        self.dirty = !settled;
+        self.refreshed = Instant::now();
+        self.show()
+    }
+
+    // Passes the whole image on to the readers.
+    fn show(&self) -> Result<(), TracerError> {
+        self.out.write(ImageBufferEvent::BufferUpdate {
+            rgb: self
+                .buffer
+                .iter()
+                .enumerate()
+                .map(|(index, color)| self.shown(index, color))
+                .collect(),
+            r: 0,
+            c: 0,
+            width: self.image.width,
+            height: self.image.height,
         })
     }
 
+    // The pixel as shown in the window. The right side of the divider
+    // shows something else in the split view.
+    fn shown(&self, index: usize, color: &Color) -> Color {
+        let column = index % self.image.width;
+        match self.split {
+            Split::Off => *color,
+            _ if column < self.divider => *color,
+            _ if column == self.divider => Color::new(1.0, 1.0, 1.0),
+            Split::ToneMap => self.post_process.process_with(
+                index,
+                &self.linear[index],
+                Some(self.split_tone_map.as_ref()),
+            ),
+            Split::Reference => match &self.options.reference {
+                Some(reference) if reference.linear() => {
+                    self.post_process.process(index, &reference.buffer()[index])
+                }
+                Some(reference) => reference.buffer()[index],
+                None => *color,
+            },
+            Split::Difference => false_color(self.difference.get(index).copied().unwrap_or(0.0)),
+        }
+    }
+
+    // Compares the image with the reference for the difference view.
+    // References that aren't linear are compared with the image as
+    // shown. Returns the metrics to log.
+    fn compare(&mut self) -> Option<String> {
+        let reference = self.options.reference.as_ref()?;
+        let decode = |c: &Color| {
+            Color::new(
+                srgb_to_linear(*c.x()),
+                srgb_to_linear(*c.y()),
+                srgb_to_linear(*c.z()),
+            )
+        };
+        let comparison = match reference.linear() {
+            true => compare(&self.image, &self.linear, reference.buffer()),
+            false => compare(
+                &self.image,
+                &self.buffer.iter().map(decode).collect::<Vec<Color>>(),
+                &reference
+                    .buffer()
+                    .iter()
+                    .map(decode)
+                    .collect::<Vec<Color>>(),
+            ),
+        };
+        self.difference = comparison.error;
+        Some(format!("Difference to reference: {}", comparison.metrics))
+    }
+
+    fn tone_map_name(&self, offset: usize) -> &'static str {
+        let tone_maps = &self.options.tone_maps;
+        tone_maps[(self.options.tone_map + offset) % tone_maps.len()].name()
+    }
+
+    // Returns a message to log about the change.
+    pub fn command(&mut self, command: DisplayCommand) -> Result<Option<String>, TracerError> {
+        match command {
+            DisplayCommand::ToggleEffects => {
+                let enabled = self.post_process.toggle_effects();
+                self.refresh(false)
+                    .map(|_| Some(format!("Bloom {}.", if enabled { "on" } else { "off" })))
+            }
+            DisplayCommand::CycleToneMap => {
+                let tone_maps = &self.options.tone_maps;
+                self.options.tone_map = (self.options.tone_map + 1) % tone_maps.len();
+                self.post_process
+                    .set_tone_map((&tone_maps[self.options.tone_map]).into());
+                self.split_tone_map =
+                    (&tone_maps[(self.options.tone_map + 1) % tone_maps.len()]).into();
+                self.refresh(false)
+                    .map(|_| Some(format!("Tone map: {}", self.tone_map_name(0))))
+            }
+            DisplayCommand::Split(split) => {
+                self.split = split;
+                let metrics = match split {
+                    Split::Difference => self.compare(),
+                    _ => None,
+                };
+                self.show().map(|_| match split {
+                    Split::Off => Some(String::from("Split view off.")),
+                    Split::ToneMap => Some(format!(
+                        "Split view: {} | {}",
+                        self.tone_map_name(0),
+                        self.tone_map_name(1)
+                    )),
+                    Split::Reference => Some(String::from("Split view: render | reference")),
+                    Split::Difference => metrics,
+                })
+            }
+            DisplayCommand::Divider(column) => {
+                self.divider = column.min(self.image.width);
+                self.show().map(|_| None)
+            }
+        }
+    }
+
+    // The tone map in use.
+    pub fn tone_map(&self) -> &ToneMapConfig {
+        &self.options.tone_maps[self.options.tone_map]
+    }
+
+    // Brings stages that need the whole image up to date with the
+    // finished image. Returns the metrics to log when the difference
+    // view is on.
+    pub fn flush(&mut self) -> Result<Option<String>, TracerError> {
+        if self.post_process.has_image_stages() {
+            self.refresh(true)?;
+        }
+        match self.split {
+            Split::Difference => {
+                let metrics = self.compare();
+                self.show().map(|_| metrics)
+            }
+            _ => Ok(None),
+        }
+    }
+
     pub fn get_writer(&self) -> DataWriter<ImageBufferEvent> {
         self.bus.get_writer()
     }
@@ -176,4 +433,8 @@ impl ScreenBuffer {
     pub fn rgb(&self) -> &[Color] {
         &self.buffer
     }
+
+    pub fn linear(&self) -> &[Color] {
+        &self.linear
+    }
 }
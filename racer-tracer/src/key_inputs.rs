@@ -6,16 +6,42 @@ use crate::error::TracerError;
 pub enum ListenKeyEvents {
     Release(Vec<Key>),
     Down(Vec<Key>),
+    // Modifier and key pairs. Triggers when the key is released while
+    // the modifier is held down.
+    Chord(Vec<(Key, Key)>),
     MouseMove(MouseButton),
+    Scroll,
 }
 
 #[derive(Clone)]
 pub enum KeyEvent {
     Released(Key),
     Down(Key),
+    Chord(Key, Key),
     MouseDelta(MouseButton, f64, f64),
+    // Sent when the button is released with where the drag started
+    // and ended.
+    MouseDrag(MouseButton, MousePos, MousePos),
+    Scroll(f64),
 }
 
+// Left and right modifiers are treated the same.
+fn modifier_down(window: &Window, modifier: Key) -> bool {
+    let other = match modifier {
+        Key::LeftCtrl => Key::RightCtrl,
+        Key::RightCtrl => Key::LeftCtrl,
+        Key::LeftShift => Key::RightShift,
+        Key::RightShift => Key::LeftShift,
+        Key::LeftAlt => Key::RightAlt,
+        Key::RightAlt => Key::LeftAlt,
+        Key::LeftSuper => Key::RightSuper,
+        Key::RightSuper => Key::LeftSuper,
+        key => key,
+    };
+    window.is_key_down(modifier) || window.is_key_down(other)
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct MousePos {
     pub x: f64,
     pub y: f64,
@@ -25,6 +51,7 @@ pub struct Mouse {
     move_on_press: MouseButton,
     mouse_down: bool,
     delta: MousePos,
+    drag_start: MousePos,
 }
 
 impl Mouse {
@@ -33,6 +60,7 @@ impl Mouse {
             move_on_press: key,
             mouse_down: false,
             delta: MousePos { x: 0.0, y: 0.0 },
+            drag_start: MousePos { x: 0.0, y: 0.0 },
         }
     }
 
@@ -43,6 +71,7 @@ impl Mouse {
                 if !self.mouse_down {
                     self.delta.x = x as f64;
                     self.delta.y = y as f64;
+                    self.drag_start = self.delta;
                 }
 
                 // Only send events when there is a diff.
@@ -62,6 +91,13 @@ impl Mouse {
 
             self.mouse_down = true;
         } else {
+            if self.mouse_down && self.drag_start != self.delta {
+                res = Some(KeyEvent::MouseDrag(
+                    self.move_on_press,
+                    self.drag_start,
+                    self.delta,
+                ));
+            }
             self.mouse_down = false;
         }
 
@@ -75,7 +111,9 @@ pub struct KeyInputs {
     key_reader: DataReader<KeyEvent>,
     listen_is_down: Vec<Key>,
     listen_is_released: Vec<Key>,
+    listen_chords: Vec<(Key, Key)>,
     listen_mouse: Vec<Mouse>,
+    listen_scroll: bool,
 }
 
 impl<'window> KeyInputs {
@@ -87,7 +125,9 @@ impl<'window> KeyInputs {
             bus,
             listen_is_down: vec![],
             listen_is_released: vec![],
+            listen_chords: vec![],
             listen_mouse: vec![],
+            listen_scroll: false,
         }
     }
 
@@ -95,9 +135,11 @@ impl<'window> KeyInputs {
         inputs.into_iter().for_each(|input| match input {
             ListenKeyEvents::Release(mut keys) => self.listen_is_released.append(&mut keys),
             ListenKeyEvents::Down(mut keys) => self.listen_is_down.append(&mut keys),
+            ListenKeyEvents::Chord(mut chords) => self.listen_chords.append(&mut chords),
             ListenKeyEvents::MouseMove(mouse_key) => {
                 self.listen_mouse.push(Mouse::new(mouse_key));
             }
+            ListenKeyEvents::Scroll => self.listen_scroll = true,
         });
     }
 
@@ -130,15 +172,37 @@ impl<'window> KeyInputs {
                 .filter(|v| v.is_some())
                 .try_for_each(|v| self.key_writer.write(v.expect("some value to be some")))?;
 
+            if self.listen_scroll {
+                if let Some((_, y)) = window.get_scroll_wheel() {
+                    self.key_writer.write(KeyEvent::Scroll(f64::from(y)))?;
+                }
+            }
+
             self.listen_is_down
                 .iter()
                 .filter(|key| window.is_key_down(**key))
                 .try_for_each(|key| self.key_writer.write(KeyEvent::Down(*key)))?;
 
+            let chords = self
+                .listen_chords
+                .iter()
+                .filter(|(modifier, key)| {
+                    window.is_key_released(*key) && modifier_down(window, *modifier)
+                })
+                .copied()
+                .collect::<Vec<(Key, Key)>>();
+
+            // A key that completed a chord should not also trigger on
+            // its own.
             self.listen_is_released
                 .iter()
                 .filter(|key| window.is_key_released(**key))
-                .try_for_each(|key| self.key_writer.write(KeyEvent::Released(*key)))
+                .filter(|key| !chords.iter().any(|(_, k)| k == *key))
+                .try_for_each(|key| self.key_writer.write(KeyEvent::Released(*key)))?;
+
+            chords.into_iter().try_for_each(|(modifier, key)| {
+                self.key_writer.write(KeyEvent::Chord(modifier, key))
+            })
         } else {
             Ok(())
         }
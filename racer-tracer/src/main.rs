@@ -1,28 +1,37 @@
 #[macro_use]
 mod error;
 mod aabb;
+mod aperture;
+mod api;
 mod background_color;
 mod bvh_node;
 mod camera;
+mod compare;
 mod config;
+mod controls;
 mod data_bus;
 mod geometry;
 mod geometry_creation;
+mod highlight;
+mod hud;
 mod image;
 mod image_action;
 mod image_buffer;
 mod key_inputs;
 mod material;
+mod post_process;
 mod ray;
 mod renderer;
 mod scene;
 mod scene_controller;
 mod shared_scene;
+mod shutter;
 mod terminal;
 mod texture;
 mod tone_map;
 mod util;
 mod vec3;
+mod worker;
 
 extern crate image as img;
 
@@ -49,65 +58,121 @@ use crate::{
     background_color::BackgroundColor,
     bvh_node::BoundingVolumeHirearchy,
     camera::{CameraData, CameraInitData},
-    config::SceneLoaderConfig as CLoader,
-    image_action::ImageAction,
-    image_buffer::{ImageBuffer, ScreenBuffer},
-    renderer::Renderer,
-    scene::{
-        none::NoneLoader, random::Random, sandbox::Sandbox, yml::YmlLoader, Scene, SceneLoader,
-    },
+    image_action::{FinishedImage, ImageActions},
+    image_buffer::{DisplayCommand, DisplayOptions, ImageBuffer, ScreenBuffer, Split},
+    post_process::PostProcessPipeline,
+    renderer::{image::ImageDisplayer, report::RenderReport, Renderer},
+    scene::{Scene, SceneLoader},
     scene_controller::{interactive::InteractiveScene, SceneController},
-    tone_map::ToneMap,
-    vec3::Vec3,
 };
 
 use crate::{
     camera::Camera,
-    config::{Args, Config},
+    config::{Args, Command, CompareMetric, Config, PostProcessConfig, ToneMapConfig},
+    controls::{Action, Binding, Controls},
     error::TracerError,
-    key_inputs::KeyInputs,
+    highlight::Highlight,
+    hud::Hud,
+    key_inputs::{KeyEvent, KeyInputs},
 };
 
-fn run(config: Config, log: Logger, _term: Terminal) -> Result<(), TracerError> {
+// The post process stages with the last tone map replaced, or added
+// if there is none.
+fn with_tone_map(stages: &[PostProcessConfig], tone_map: &ToneMapConfig) -> Vec<PostProcessConfig> {
+    let mut stages = stages.to_vec();
+    match stages
+        .iter_mut()
+        .rev()
+        .find(|stage| matches!(stage, PostProcessConfig::ToneMap(_)))
+    {
+        Some(stage) => *stage = PostProcessConfig::ToneMap(tone_map.clone()),
+        None => stages.push(PostProcessConfig::ToneMap(tone_map.clone())),
+    }
+    stages
+}
+
+fn run(config: Config, log: Logger, mut term: Terminal) -> Result<(), TracerError> {
     info!(log, "Starting racer-tracer {}", env!("CARGO_PKG_VERSION"));
+    let controls = Controls::try_from(&config.controls)?;
     let image = image::Image::new(config.screen.width, config.screen.height);
-    let loader = match &config.loader {
-        CLoader::Yml { path } => Box::new(YmlLoader::new(path.clone())) as Box<dyn SceneLoader>,
-        CLoader::Random => Box::new(Random::new()) as Box<dyn SceneLoader>,
-        CLoader::None => Box::new(NoneLoader::new()) as Box<dyn SceneLoader>,
-        CLoader::Sandbox => Box::new(Sandbox::new()) as Box<dyn SceneLoader>,
-    };
+    let loader: Box<dyn SceneLoader> = (&config).into();
 
     let scene_data = loader.load()?;
     let background = &*scene_data.background as &dyn BackgroundColor;
-    let image_action: &dyn ImageAction = (&config.image_action).into();
-    let tone_map: Box<dyn ToneMap> = scene_data
-        .tone_map
-        .unwrap_or_else(|| (&config.tone_map).into());
+    let image_actions = Arc::new(ImageActions::from(config.image_actions.as_slice()));
+    let mut image_action_threads = Vec::new();
+    // The scene can pick its own post processing and tone map. Without
+    // any post process stages only the tone map is applied. A tone map
+    // of the scene takes the place of the one in the stages.
+    let post_process_config = scene_data
+        .post_process
+        .clone()
+        .unwrap_or_else(|| config.post_process.clone());
+    let post_process_config = match (&scene_data.tone_map, post_process_config.is_empty()) {
+        (tone_map, true) => vec![PostProcessConfig::ToneMap(
+            tone_map.clone().unwrap_or_else(|| config.tone_map.clone()),
+        )],
+        (Some(tone_map), false) => with_tone_map(&post_process_config, tone_map),
+        (None, false) => post_process_config,
+    };
+    let post_process =
+        PostProcessPipeline::new(&post_process_config, &config.output_transform, &log)?;
+
+    // Tone maps to switch between. The one in use is added to the list
+    // if it isn't in it.
+    let mut tone_maps = if config.tone_maps.is_empty() {
+        ToneMapConfig::all()
+    } else {
+        config.tone_maps.clone()
+    };
+    let tone_map = post_process_config
+        .iter()
+        .rev()
+        .find_map(|stage| match stage {
+            PostProcessConfig::ToneMap(tone_map) => Some(tone_map.clone()),
+            _ => None,
+        })
+        .unwrap_or(ToneMapConfig::None);
+    let tone_map = match tone_maps.iter().position(|t| t.name() == tone_map.name()) {
+        Some(index) => {
+            tone_maps[index] = tone_map;
+            index
+        }
+        None => {
+            tone_maps.insert(0, tone_map);
+            0
+        }
+    };
+    let reference = config
+        .reference
+        .as_ref()
+        .map(|path| ImageDisplayer::try_new(path, &image))
+        .transpose()?;
+    let has_reference = reference.is_some();
 
     let mut screen_data_buffer = vec![0; image.width * image.height];
+    let mut overlay_buffer = vec![0; image.width * image.height];
+    let mut hud = Hud::new(config.screen.hud);
+    let mut highlight = Highlight::new();
     let mut image_buffer = ImageBuffer::new(image.clone());
-    let mut screen_buffer =
-        ScreenBuffer::new(image.clone(), image_buffer.get_data_writer(), tone_map);
+    let mut screen_buffer = ScreenBuffer::new(
+        image.clone(),
+        image_buffer.get_data_writer(),
+        post_process,
+        DisplayOptions {
+            tone_maps,
+            tone_map,
+            reference,
+        },
+    );
     let screen_buffer_writer = screen_buffer.get_writer();
     let mut image_buffer_reader = image_buffer.get_reader();
 
     let camera_data =
         CameraData::merge(scene_data.camera.unwrap_or_default(), config.camera.clone());
-    let mut camera = Camera::new(
-        CameraInitData {
-            look_from: camera_data.pos,
-            look_at: camera_data.look_at,
-            scene_up: Vec3::new(0.0, 1.0, 0.0),
-            vfov: camera_data.vfov,
-            aperture: camera_data.aperture,
-            focus_distance: camera_data.focus_distance,
-            aspect_ratio: image.aspect_ratio,
-            time_a: 0.0,
-            time_b: 1.0,
-        },
-        &image,
-    );
+    let camera_init = CameraInitData::try_new(&camera_data, &image)?;
+    let shutter = camera_init.shutter.clone();
+    let mut camera = Camera::new(camera_init, &image);
     let mut shared_camera = camera.get_shared_camera();
 
     let mut scene = Scene::new(
@@ -116,43 +181,98 @@ fn run(config: Config, log: Logger, _term: Terminal) -> Result<(), TracerError>
         scene_data.objects,
     );
     let (objs, reader) = scene.get_shared_objects();
-    let mut bvh = BoundingVolumeHirearchy::new(objs, reader, 0.0, 1.0);
+    let mut bvh = BoundingVolumeHirearchy::new(objs, reader, shutter.open(), shutter.close());
     let (render_sender, render_receiver) = std::sync::mpsc::channel::<Result<(), TracerError>>();
     let mut window_res: Result<(), TracerError> = Ok(());
     let mut screen_buffer_res: Result<(), TracerError> = Ok(());
     let render_exit = Arc::new(SignalEvent::manual(false));
     let update_exit = Arc::clone(&render_exit);
     let image_action_signal = Arc::new(SignalEvent::manual(false));
+    let (display_sender, display_receiver) = std::sync::mpsc::channel::<DisplayCommand>();
+    let mut split = Split::Off;
 
-    let renderer: Box<dyn Renderer> = (&config.renderer, &config.render, &image).into();
+    let renderer: Box<dyn Renderer> = (&config.renderer, &config.render, &log).into();
     let renderer_preview: Box<dyn Renderer> =
-        (&config.preview_renderer, &config.preview, &image).into();
+        (&config.preview_renderer, &config.preview, &log).into();
 
     let scene_controller = {
         match &config.scene_controller {
             config::SceneControllerConfig::Interactive => InteractiveScene::new(
                 log.new(o!("scope" => "scene-controller")),
                 config.clone(),
+                controls.clone(),
                 image.clone(),
                 camera_data,
+                scene_data.sha256.clone(),
                 renderer,
                 renderer_preview,
             ),
         }
     };
 
+    let progress = scene_controller.render_progress();
+    let api = config
+        .api
+        .as_ref()
+        .map(|address| {
+            api::Api::start(
+                log.new(o!("scope" => "api")),
+                address,
+                image.clone(),
+                Arc::clone(&progress),
+            )
+        })
+        .transpose()?;
     let mut inputs = KeyInputs::new();
     inputs.register_inputs(scene_controller.register_key_inputs());
 
     rayon::scope(|s| {
         s.spawn(|_| {
             let logger = log.new(o!("scope" => "screen-buffer"));
+            // Moved in since the receiver can't be shared.
+            let display_receiver = display_receiver;
             while !update_exit.status() && screen_buffer_res.is_ok() {
                 screen_buffer_res = screen_buffer.update();
 
+                if screen_buffer_res.is_ok() {
+                    screen_buffer_res = display_receiver.try_iter().try_for_each(|command| {
+                        screen_buffer.command(command).map(|message| {
+                            if let Some(message) = message {
+                                info!(logger, "{}", message)
+                            }
+                        })
+                    });
+                }
+
                 if screen_buffer_res.is_ok() && image_action_signal.status() {
                     image_action_signal.reset();
-                    screen_buffer_res = image_action.action(screen_buffer.rgb(), &config, &logger);
+                    screen_buffer_res = screen_buffer.flush().map(|message| {
+                        if let Some(message) = message {
+                            info!(logger, "{}", message)
+                        }
+                    });
+                    if screen_buffer_res.is_ok() && !image_actions.is_empty() {
+                        let report = scene_controller.last_render().map(|render| {
+                            let stages =
+                                with_tone_map(&post_process_config, screen_buffer.tone_map());
+                            RenderReport::new(&config, &stages, &render)
+                        });
+                        let image = FinishedImage::new(
+                            screen_buffer.rgb().to_vec(),
+                            screen_buffer.linear().to_vec(),
+                            report,
+                        );
+                        // Commands can take a while so the actions run
+                        // on their own thread to keep the window going.
+                        let (actions, config, logger) =
+                            (Arc::clone(&image_actions), config.clone(), logger.clone());
+                        image_action_threads.push(std::thread::spawn(move || {
+                            let failed = actions.run(&image, &config, &logger);
+                            if failed > 0 {
+                                warn!(logger, "{} image actions failed.", failed);
+                            }
+                        }));
+                    }
                 }
             }
         });
@@ -221,24 +341,99 @@ fn run(config: Config, log: Logger, _term: Terminal) -> Result<(), TracerError>
                 {
                     let dt = t.elapsed().as_micros() as f64;
                     t = Instant::now();
+                    hud.update_fps(dt);
+                    term.update_progress(&progress.info());
+                    let overlay_visible = hud.visible()
+                        || scene.selected_object().is_some()
+                        || scene_controller.render_region().is_some();
                     res = inputs
                         .update(&mut window)
                         .and_then(|_| scene.update())
                         .and_then(|_| camera.update())
                         .and_then(|_| inputs.get_presses())
                         .and_then(|key_presses| {
-                            scene_controller.update(
-                                dt,
-                                key_presses,
-                                inputs.get_mouse_pos(&mut window),
-                                &mut camera,
-                                &mut scene,
-                            )
+                            let mut commands = Vec::new();
+                            key_presses
+                                .iter()
+                                .for_each(|event| match controls.pressed(event) {
+                                    Some(Action::ToggleHud) => hud.toggle(),
+                                    Some(Action::ToggleBoundingBox) => {
+                                        highlight.toggle_bounding_box()
+                                    }
+                                    Some(Action::ToggleBloom) => {
+                                        commands.push(DisplayCommand::ToggleEffects)
+                                    }
+                                    Some(Action::CycleToneMap) => {
+                                        commands.push(DisplayCommand::CycleToneMap)
+                                    }
+                                    Some(Action::ToggleSplit) => {
+                                        split = split.next(has_reference);
+                                        commands.push(DisplayCommand::Split(split))
+                                    }
+                                    _ => (),
+                                });
+
+                            // Looking around moves the divider in the
+                            // split view instead.
+                            let mouse_pos = inputs.get_mouse_pos(&mut window);
+                            let key_presses = if split == Split::Off {
+                                key_presses
+                            } else {
+                                key_presses
+                                    .into_iter()
+                                    .filter(|event| match event {
+                                        KeyEvent::MouseDelta(button, _, _)
+                                        | KeyEvent::MouseDrag(button, _, _)
+                                            if controls.action(&Binding::Mouse(*button))
+                                                == Some(Action::Look) =>
+                                        {
+                                            if let Some(pos) = &mouse_pos {
+                                                commands.push(DisplayCommand::Divider(
+                                                    pos.x.max(0.0) as usize,
+                                                ))
+                                            }
+                                            false
+                                        }
+                                        _ => true,
+                                    })
+                                    .collect()
+                            };
+
+                            commands
+                                .into_iter()
+                                .try_for_each(|command| {
+                                    display_sender
+                                        .send(command)
+                                        .map_err(|e| TracerError::SendError(e.to_string()))
+                                })
+                                .and_then(|_| {
+                                    scene_controller.update(
+                                        dt,
+                                        key_presses,
+                                        mouse_pos,
+                                        &mut camera,
+                                        &mut scene,
+                                    )
+                                })
                         })
                         .and_then(|_| image_buffer.update())
                         .and_then(|_| image_buffer_reader.update())
+                        .and_then(|_| match &api {
+                            Some(api) => api.requests().map(|requests| {
+                                requests.into_iter().for_each(|request| {
+                                    request.apply(
+                                        &mut camera,
+                                        &mut scene,
+                                        scene_controller,
+                                        image_buffer_reader.rgb(),
+                                    )
+                                })
+                            }),
+                            None => Ok(()),
+                        })
                         .and_then(|_| {
-                            if image_buffer_reader.changed() {
+                            let changed = image_buffer_reader.changed();
+                            if changed {
                                 for (i, c) in image_buffer_reader.rgb().iter().enumerate() {
                                     let red: u32 = (c.x() * 255.0) as u32;
                                     let green: u32 = (c.y() * 255.0) as u32;
@@ -247,6 +442,44 @@ fn run(config: Config, log: Logger, _term: Terminal) -> Result<(), TracerError>
                                     screen_data_buffer[i] =
                                         (255 << 24) | (red << 16) | green << 8 | blue;
                                 }
+                            }
+
+                            let selected = scene.selected_scene_object();
+                            let region = scene_controller.render_region();
+                            if hud.visible() || selected.is_some() || region.is_some() {
+                                // Overlays change every frame so they are
+                                // drawn on a copy to keep the image intact.
+                                overlay_buffer.copy_from_slice(&screen_data_buffer);
+                                if let Some(object) = selected {
+                                    highlight.draw(
+                                        &camera,
+                                        object,
+                                        image_buffer_reader.obj_id(),
+                                        &mut overlay_buffer,
+                                        image.width,
+                                        image.height,
+                                    );
+                                }
+                                if let Some(region) = region {
+                                    highlight.draw_region(
+                                        &region,
+                                        &mut overlay_buffer,
+                                        image.width,
+                                        image.height,
+                                    );
+                                }
+                                if hud.visible() {
+                                    hud.draw(
+                                        &hud.lines(&camera, &scene, &progress.info()),
+                                        &mut overlay_buffer,
+                                        image.width,
+                                        image.height,
+                                    );
+                                }
+                                window
+                                    .update_with_buffer(&overlay_buffer, image.width, image.height)
+                                    .map_err(|e| TracerError::FailedToUpdateWindow(e.to_string()))
+                            } else if changed || overlay_visible {
                                 window
                                     .update_with_buffer(
                                         &screen_data_buffer,
@@ -268,6 +501,13 @@ fn run(config: Config, log: Logger, _term: Terminal) -> Result<(), TracerError>
         })
     });
 
+    // Let the image actions finish before exiting.
+    image_action_threads.into_iter().for_each(|thread| {
+        if thread.join().is_err() {
+            error!(log, "An image action panicked.");
+        }
+    });
+
     // Could technically be more but we only expect a single one.
     let render_res = render_receiver
         .recv_timeout(Duration::from_millis(0))
@@ -321,8 +561,88 @@ fn bridge_main(config: Config) -> i32 {
         }
     }
 }
+
+fn bridge_worker(listen: &str) -> i32 {
+    // Several workers can run on the same machine.
+    let log_file =
+        std::env::temp_dir().join(format!("racer-tracer-worker-{}.log", std::process::id()));
+    let log = create_log(log_file.as_ref()).expect("Expected to be able to create a log");
+    info!(log, "Log file: {}", log_file.display());
+
+    match worker::run_worker(log.new(o!("scope" => "worker")), listen) {
+        Ok(_) => 0,
+        Err(e) => {
+            error!(log, "Error: {}", e);
+            i32::from(e)
+        }
+    }
+}
+
+// Prints the metrics so it can be used from scripts. The exit code is
+// non-zero when the images differ more than the threshold.
+fn bridge_compare(
+    image: &Path,
+    reference: &Path,
+    output: Option<&Path>,
+    metric: &CompareMetric,
+    threshold: Option<f64>,
+) -> i32 {
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| {
+        let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+        image.with_file_name(format!("{}_difference.png", stem))
+    });
+    let res = compare::compare_files(image, reference).and_then(|(size, comparison)| {
+        println!("{}", comparison.metrics);
+        compare::save_difference(&output, &size, &comparison)?;
+        println!("Difference image: {}", output.display());
+        match threshold {
+            Some(threshold) if comparison.metrics.exceeds(metric, threshold) => {
+                Err(TracerError::Compare(format!(
+                    "{} {:.6} is {} the threshold {}",
+                    metric.name(),
+                    comparison.metrics.get(metric),
+                    if *metric == CompareMetric::Psnr {
+                        "below"
+                    } else {
+                        "over"
+                    },
+                    threshold
+                )))
+            }
+            _ => Ok(()),
+        }
+    });
+
+    match res {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            i32::from(e)
+        }
+    }
+}
+
 fn main() {
-    match Config::try_from(Args::from_args()).map(bridge_main) {
+    let args = Args::from_args();
+    match &args.command {
+        Some(Command::Worker { listen }) => std::process::exit(bridge_worker(listen)),
+        Some(Command::Compare {
+            image,
+            reference,
+            output,
+            metric,
+            threshold,
+        }) => std::process::exit(bridge_compare(
+            image,
+            reference,
+            output.as_deref(),
+            metric,
+            *threshold,
+        )),
+        None => (),
+    }
+
+    match Config::try_from(args).map(bridge_main) {
         Ok(ec) => std::process::exit(ec),
         Err(e) => {
             println!("Failed to parse config file: {}", e);
@@ -0,0 +1,334 @@
+pub mod auto_exposure;
+pub mod bloom;
+pub mod color_grade;
+pub mod exposure;
+pub mod lut;
+pub mod output;
+pub mod white_balance;
+
+use rayon::prelude::*;
+use slog::Logger;
+
+use crate::{
+    config::{OutputTransformConfig, PostProcessConfig},
+    error::TracerError,
+    image::Image,
+    tone_map::ToneMap,
+    vec3::{Color, Vec3},
+};
+
+use self::{
+    auto_exposure::AutoExposure,
+    bloom::{Bloom, Glare},
+    color_grade::ColorGrade,
+    exposure::Exposure,
+    lut::Lut,
+    output::OutputTransform,
+    white_balance::WhiteBalance,
+};
+
+// Rec. 709 luminance of a linear color.
+pub fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+// Matrices are stored as rows.
+pub fn mul(matrix: &[Vec3; 3], v: &Vec3) -> Vec3 {
+    Vec3::new(matrix[0].dot(v), matrix[1].dot(v), matrix[2].dot(v))
+}
+
+pub fn mul_matrix(a: &[Vec3; 3], b: &[Vec3; 3]) -> [Vec3; 3] {
+    let column = |i: usize| Vec3::new(b[0][i], b[1][i], b[2][i]);
+    let row = |r: &Vec3| Vec3::new(r.dot(&column(0)), r.dot(&column(1)), r.dot(&column(2)));
+    [row(&a[0]), row(&a[1]), row(&a[2])]
+}
+
+pub trait PostProcess: Send + Sync {
+    fn process(&self, color: &Color) -> Color;
+}
+
+// Effects that need the whole image. They are updated from the full
+// image every now and then and the result is applied per pixel in
+// between so parts of the image can be processed as they come in.
+pub trait ImageProcess: Send + Sync {
+    // The buffer holds the image as processed by the earlier stages.
+    // Settle is set for finished images, stages that change over time
+    // should go straight to where they would end up. Returns false
+    // while the stage is still changing and needs more updates.
+    fn update(&mut self, image: &Image, buffer: &[Color], settle: bool) -> bool;
+    fn process(&self, index: usize, color: &Color) -> Color;
+}
+
+enum Stage {
+    Pixel(Box<dyn PostProcess>),
+    // Kept apart so it can be switched while running.
+    ToneMap(Box<dyn ToneMap>),
+    Image(Box<dyn ImageProcess>),
+    // Image stages that can be turned off, like bloom.
+    Effect(Box<dyn ImageProcess>),
+}
+
+// Stages applied in order to every pixel of the rendered image before
+// it is shown or saved.
+pub struct PostProcessPipeline {
+    stages: Vec<Stage>,
+    // Effects can be turned off to compare with and without.
+    effects: bool,
+}
+
+impl PostProcessPipeline {
+    // Index is the position of the pixel in the image.
+    pub fn process(&self, index: usize, color: &Color) -> Color {
+        self.process_with(index, color, None)
+    }
+
+    // Like process but with another tone map in place of the current
+    // one.
+    pub fn process_with(
+        &self,
+        index: usize,
+        color: &Color,
+        tone_map: Option<&dyn ToneMap>,
+    ) -> Color {
+        self.stages.iter().fold(*color, |color, stage| match stage {
+            Stage::Pixel(stage) => stage.process(&color),
+            Stage::ToneMap(own) => tone_map.unwrap_or(own.as_ref()).tone_map(&color),
+            Stage::Image(stage) => stage.process(index, &color),
+            Stage::Effect(stage) if self.effects => stage.process(index, &color),
+            Stage::Effect(_) => color,
+        })
+    }
+
+    // Processes the whole image and updates the image stages on the
+    // way. Also returns if the image stages have settled.
+    pub fn process_image(
+        &mut self,
+        image: &Image,
+        linear: &[Color],
+        settle: bool,
+    ) -> (Vec<Color>, bool) {
+        let mut buffer = linear.to_vec();
+        let mut settled = true;
+        for stage in self.stages.iter_mut() {
+            match stage {
+                Stage::Pixel(stage) => buffer
+                    .par_iter_mut()
+                    .for_each(|color| *color = stage.process(color)),
+                Stage::ToneMap(tone_map) => buffer
+                    .par_iter_mut()
+                    .for_each(|color| *color = tone_map.tone_map(color)),
+                Stage::Image(stage) => {
+                    settled &= Self::update_stage(stage, image, &mut buffer, settle)
+                }
+                Stage::Effect(stage) if self.effects => {
+                    settled &= Self::update_stage(stage, image, &mut buffer, settle)
+                }
+                Stage::Effect(_) => (),
+            }
+        }
+        (buffer, settled)
+    }
+
+    fn update_stage(
+        stage: &mut Box<dyn ImageProcess>,
+        image: &Image,
+        buffer: &mut [Color],
+        settle: bool,
+    ) -> bool {
+        let settled = stage.update(image, buffer, settle);
+        buffer
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, color)| *color = stage.process(index, color));
+        settled
+    }
+
+    pub fn has_image_stages(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|stage| matches!(stage, Stage::Image(_) | Stage::Effect(_)))
+    }
+
+    // Replaces the last tone map. Added before the output transform if
+    // there is none.
+    pub fn set_tone_map(&mut self, tone_map: Box<dyn ToneMap>) {
+        let current = self.stages.iter_mut().rev().find_map(|stage| match stage {
+            Stage::ToneMap(current) => Some(current),
+            _ => None,
+        });
+        match current {
+            Some(current) => *current = tone_map,
+            None => self
+                .stages
+                .insert(self.stages.len() - 1, Stage::ToneMap(tone_map)),
+        }
+    }
+
+    // Returns if effects are enabled after the toggle.
+    pub fn toggle_effects(&mut self) -> bool {
+        self.effects = !self.effects;
+        self.effects
+    }
+
+    // The output transform is added after the stages.
+    pub fn new(
+        stages: &[PostProcessConfig],
+        output: &OutputTransformConfig,
+        log: &Logger,
+    ) -> Result<Self, TracerError> {
+        stages
+            .iter()
+            .map(|stage| -> Result<Stage, TracerError> {
+                Ok(match stage {
+                    PostProcessConfig::Exposure { ev } => {
+                        Stage::Pixel(Box::new(Exposure::new(*ev)))
+                    }
+                    PostProcessConfig::WhiteBalance { temperature, tint } => Stage::Pixel(
+                        Box::new(WhiteBalance::new(*temperature, tint.unwrap_or_default())),
+                    ),
+                    PostProcessConfig::ColorGrade {
+                        contrast,
+                        saturation,
+                    } => Stage::Pixel(Box::new(ColorGrade::new(
+                        contrast.unwrap_or(1.0),
+                        saturation.unwrap_or(1.0),
+                    ))),
+                    PostProcessConfig::Lut { path } => Stage::Pixel(Box::new(Lut::load(path)?)),
+                    PostProcessConfig::ToneMap(tone_map) => Stage::ToneMap(tone_map.into()),
+                    PostProcessConfig::Bloom {
+                        default: _,
+                        threshold,
+                        intensity,
+                        radius,
+                        levels,
+                        glare,
+                    } => Stage::Effect(Box::new(Bloom::new(
+                        threshold.unwrap_or(1.0),
+                        intensity.unwrap_or(0.1),
+                        match radius.unwrap_or(2.0) {
+                            // The blur has nothing to spread the light with.
+                            radius if radius <= 0.0 || radius.is_nan() => {
+                                return Err(TracerError::PostProcess(format!(
+                                    "Bloom radius has to be above 0, got {}",
+                                    radius
+                                )))
+                            }
+                            radius => radius,
+                        },
+                        levels.unwrap_or(5),
+                        glare.as_ref().map(|glare| Glare {
+                            streaks: glare.streaks.unwrap_or(4),
+                            length: glare.length.unwrap_or(50.0),
+                            intensity: glare.intensity.unwrap_or(0.05),
+                            rotation: glare.rotation.unwrap_or(45.0).to_radians(),
+                        }),
+                    ))),
+                    PostProcessConfig::AutoExposure {
+                        default: _,
+                        low,
+                        high,
+                        key,
+                        speed,
+                    } => {
+                        let (low, high) = (low.unwrap_or(10.0), high.unwrap_or(90.0));
+                        let (key, speed) = (key.unwrap_or(0.18), speed.unwrap_or(2.0));
+                        let percent = 0.0..=100.0;
+                        if !percent.contains(&low) || !percent.contains(&high) || low >= high {
+                            return Err(TracerError::PostProcess(format!(
+                                "Auto exposure needs 0 <= low < high <= 100, got {} and {}",
+                                low, high
+                            )));
+                        }
+                        if key <= 0.0 || key.is_nan() {
+                            return Err(TracerError::PostProcess(format!(
+                                "Auto exposure key has to be above 0, got {}",
+                                key
+                            )));
+                        }
+                        if speed < 0.0 || speed.is_nan() {
+                            return Err(TracerError::PostProcess(format!(
+                                "Auto exposure speed can't be negative, got {}",
+                                speed
+                            )));
+                        }
+                        Stage::Image(Box::new(AutoExposure::new(
+                            low,
+                            high,
+                            key,
+                            speed,
+                            log.new(o!("scope" => "auto-exposure")),
+                        )))
+                    }
+                })
+            })
+            .chain(std::iter::once(Ok(Stage::Pixel(Box::new(
+                OutputTransform::new(output),
+            )))))
+            .collect::<Result<Vec<_>, TracerError>>()
+            .map(|stages| Self {
+                stages,
+                effects: true,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_bloom_without_radius() {
+        let log = Logger::root(slog::Discard, o!());
+        let bloom = |radius: f64| {
+            PostProcessPipeline::new(
+                &[PostProcessConfig::Bloom {
+                    default: None,
+                    threshold: None,
+                    intensity: None,
+                    radius: Some(radius),
+                    levels: None,
+                    glare: None,
+                }],
+                &OutputTransformConfig::Srgb,
+                &log,
+            )
+        };
+        assert!(bloom(0.5).is_ok());
+        assert!(matches!(bloom(0.0), Err(TracerError::PostProcess(_))));
+        assert!(matches!(bloom(-1.0), Err(TracerError::PostProcess(_))));
+    }
+
+    #[test]
+    fn rejects_bad_auto_exposure() {
+        let log = Logger::root(slog::Discard, o!());
+        let auto_exposure = |low: f64, high: f64, key: f64, speed: f64| {
+            PostProcessPipeline::new(
+                &[PostProcessConfig::AutoExposure {
+                    default: None,
+                    low: Some(low),
+                    high: Some(high),
+                    key: Some(key),
+                    speed: Some(speed),
+                }],
+                &OutputTransformConfig::Srgb,
+                &log,
+            )
+        };
+        assert!(auto_exposure(10.0, 90.0, 0.18, 2.0).is_ok());
+        assert!(auto_exposure(0.0, 100.0, 0.18, 0.0).is_ok());
+        for (low, high, key, speed) in [
+            (10.0, 90.0, 0.0, 2.0),
+            (10.0, 90.0, -0.18, 2.0),
+            (10.0, 90.0, 0.18, -1.0),
+            (150.0, 90.0, 0.18, 2.0),
+            (50.0, 50.0, 0.18, 2.0),
+            (-1.0, 90.0, 0.18, 2.0),
+            (10.0, 101.0, 0.18, 2.0),
+        ] {
+            assert!(matches!(
+                auto_exposure(low, high, key, speed),
+                Err(TracerError::PostProcess(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Instant;
+
+use slog::Logger;
+
+use crate::{image::Image, vec3::Color};
+
+use super::{luminance, ImageProcess};
+
+// Range of log2 luminance in the histogram. Darker pixels are left out
+// so a black background doesn't pull the exposure up.
+const MIN_LOG: f64 = -16.0;
+const MAX_LOG: f64 = 16.0;
+const BINS: usize = 128;
+
+// How much the exposure has to change before it is logged again.
+const LOG_STEP: f64 = 0.1;
+
+// Closer than this to the target is too little to see.
+const SETTLED_EV: f64 = 0.01;
+
+// Average log2 luminance of the pixels between the low and high
+// percentiles. None if the image is black.
+pub fn average_log_luminance(buffer: &[Color], low: f64, high: f64) -> Option<f64> {
+    let bin_size = (MAX_LOG - MIN_LOG) / BINS as f64;
+    let mut histogram = [0_usize; BINS];
+    for color in buffer {
+        let l = luminance(color);
+        if l > MIN_LOG.exp2() {
+            let bin = ((l.log2() - MIN_LOG) / bin_size) as usize;
+            histogram[bin.min(BINS - 1)] += 1;
+        }
+    }
+
+    let total = histogram.iter().sum::<usize>() as f64;
+    let (low, high) = (total * low / 100.0, total * high / 100.0);
+    let (mut seen, mut sum, mut count) = (0.0, 0.0, 0.0);
+    for (bin, pixels) in histogram.iter().enumerate() {
+        // The part of the bin that is inside the range.
+        let pixels = *pixels as f64;
+        let inside = (seen + pixels).min(high) - seen.max(low);
+        if inside > 0.0 {
+            sum += inside * (MIN_LOG + (bin as f64 + 0.5) * bin_size);
+            count += inside;
+        }
+        seen += pixels;
+    }
+    (count > 0.0).then(|| sum / count)
+}
+
+// Picks the exposure that brings the average luminance to the key
+// value, like the light meter of a camera. The exposure eases towards
+// it over time.
+pub struct AutoExposure {
+    low: f64,
+    high: f64,
+    key: f64,
+    // How fast the exposure adapts, about 1 / seconds.
+    speed: f64,
+    ev: Option<f64>,
+    scale: f64,
+    updated: Instant,
+    logged_ev: f64,
+    log: Logger,
+}
+
+impl AutoExposure {
+    pub fn new(low: f64, high: f64, key: f64, speed: f64, log: Logger) -> Self {
+        Self {
+            low,
+            high,
+            key,
+            speed,
+            ev: None,
+            scale: 1.0,
+            updated: Instant::now(),
+            logged_ev: f64::NAN,
+            log,
+        }
+    }
+}
+
+impl ImageProcess for AutoExposure {
+    fn update(&mut self, _image: &Image, buffer: &[Color], settle: bool) -> bool {
+        let elapsed = self.updated.elapsed().as_secs_f64();
+        self.updated = Instant::now();
+        let target = match average_log_luminance(buffer, self.low, self.high) {
+            Some(average) => self.key.log2() - average,
+            None => return true,
+        };
+
+        let ev = match self.ev {
+            Some(ev) if !settle => ev + (target - ev) * (1.0 - (-elapsed * self.speed).exp()),
+            _ => target,
+        };
+        self.ev = Some(ev);
+        self.scale = ev.exp2();
+
+        if settle || (ev - self.logged_ev).abs() >= LOG_STEP || self.logged_ev.is_nan() {
+            self.logged_ev = ev;
+            info!(self.log, "Auto exposure: {:+.2} EV", ev);
+        }
+        (target - ev).abs() < SETTLED_EV
+    }
+
+    fn process(&self, _index: usize, color: &Color) -> Color {
+        color * self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_inside_the_range() {
+        // Half the pixels at 2^-4 and half at 2^4, black is ignored.
+        let mut buffer = vec![Color::new(0.0625, 0.0625, 0.0625); 50];
+        buffer.extend(vec![Color::new(16.0, 16.0, 16.0); 50]);
+        buffer.extend(vec![Color::default(); 100]);
+
+        let all = average_log_luminance(&buffer, 0.0, 100.0).unwrap();
+        assert!(all.abs() < 0.2, "{}", all);
+        let dark = average_log_luminance(&buffer, 0.0, 50.0).unwrap();
+        assert!((dark + 4.0).abs() < 0.2, "{}", dark);
+        let bright = average_log_luminance(&buffer, 60.0, 90.0).unwrap();
+        assert!((bright - 4.0).abs() < 0.2, "{}", bright);
+
+        assert!(average_log_luminance(&[Color::default()], 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn unsettled_until_adapted() {
+        let image = Image::new(2, 2);
+        let dark = vec![Color::new(0.01, 0.01, 0.01); 4];
+        let bright = vec![Color::new(10.0, 10.0, 10.0); 4];
+        let mut exposure =
+            AutoExposure::new(0.0, 100.0, 0.18, 1.0, Logger::root(slog::Discard, o!()));
+
+        // Starts out where it should be.
+        assert!(exposure.update(&image, &dark, false));
+        // Barely any time passes so it has a long way to go.
+        assert!(!exposure.update(&image, &bright, false));
+        assert!(!exposure.update(&image, &bright, false));
+        assert!(exposure.update(&image, &bright, true));
+        assert!(exposure.update(&image, &bright, false));
+    }
+}
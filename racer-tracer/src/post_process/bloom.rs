@@ -0,0 +1,260 @@
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::{image::Image, vec3::Color};
+
+use super::{luminance, ImageProcess};
+
+// Streaks fade to this much of their strength at their length.
+const STREAK_FADE: f64 = 0.05;
+
+pub struct Glare {
+    pub streaks: usize,
+    pub length: f64,
+    pub intensity: f64,
+    // Radians.
+    pub rotation: f64,
+}
+
+// An image or a smaller version of it.
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+}
+
+impl Plane {
+    fn from_fn<F: Fn(usize, usize) -> Color + Sync>(width: usize, height: usize, f: F) -> Self {
+        Self {
+            width,
+            height,
+            data: (0..width * height)
+                .into_par_iter()
+                .map(|i| f(i % width, i / width))
+                .collect(),
+        }
+    }
+
+    fn get(&self, x: isize, y: isize) -> Color {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    // Bilinear sample at pixel coordinates where pixel centers are at
+    // whole numbers. Outside of the plane is black if clamp is off.
+    fn sample(&self, x: f64, y: f64, clamp: bool) -> Color {
+        if !clamp && (x < -1.0 || y < -1.0 || x > self.width as f64 || y > self.height as f64) {
+            return Color::default();
+        }
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let get = |x: isize, y: isize| {
+            let inside = x >= 0 && y >= 0 && x < self.width as isize && y < self.height as isize;
+            if clamp || inside {
+                self.get(x, y)
+            } else {
+                Color::default()
+            }
+        };
+        let top = get(x0, y0) * (1.0 - fx) + get(x0 + 1, y0) * fx;
+        let bottom = get(x0, y0 + 1) * (1.0 - fx) + get(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // Half the size by averaging 2x2 pixels.
+    fn downsample(&self) -> Self {
+        Self::from_fn((self.width + 1) / 2, (self.height + 1) / 2, |x, y| {
+            let (x, y) = (2 * x as isize, 2 * y as isize);
+            (self.get(x, y) + self.get(x + 1, y) + self.get(x, y + 1) + self.get(x + 1, y + 1))
+                * 0.25
+        })
+    }
+
+    fn blur(&self, sigma: f64) -> Self {
+        let radius = (3.0 * sigma).ceil() as isize;
+        let weights = (-radius..=radius)
+            .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+            .collect::<Vec<f64>>();
+        let total: f64 = weights.iter().sum();
+        let blur = |plane: &Plane, dx: isize, dy: isize| {
+            Self::from_fn(plane.width, plane.height, |x, y| {
+                weights
+                    .iter()
+                    .zip(-radius..=radius)
+                    .fold(Color::default(), |acc, (w, i)| {
+                        acc + plane.get(x as isize + i * dx, y as isize + i * dy) * *w
+                    })
+                    / total
+            })
+        };
+        blur(&blur(self, 1, 0), 0, 1)
+    }
+
+    // Smears the light along the direction in a few passes that each
+    // reach four times as far as the last.
+    fn streak(&self, direction: (f64, f64), length: f64) -> Self {
+        let fade = STREAK_FADE.powf(1.0 / length.max(1.0));
+        let mut plane = Self {
+            width: self.width,
+            height: self.height,
+            data: self.data.clone(),
+        };
+        let mut step = 1.0;
+        while step < length {
+            let weights = (0..4)
+                .map(|s| fade.powf(step * s as f64))
+                .collect::<Vec<f64>>();
+            let total: f64 = weights.iter().sum();
+            plane = Self::from_fn(plane.width, plane.height, |x, y| {
+                weights
+                    .iter()
+                    .enumerate()
+                    .fold(Color::default(), |acc, (s, w)| {
+                        let offset = step * s as f64;
+                        acc + plane.sample(
+                            x as f64 + direction.0 * offset,
+                            y as f64 + direction.1 * offset,
+                            false,
+                        ) * *w
+                    })
+                    / total
+            });
+            step *= 4.0;
+        }
+        plane
+    }
+}
+
+// Glow around the parts of the image brighter than the threshold. The
+// bright parts are blurred at several scales, each half the size of
+// the last, and added together. Everything is done at half the size
+// of the image to keep it fast enough to update while rendering.
+pub struct Bloom {
+    threshold: f64,
+    intensity: f64,
+    radius: f64,
+    levels: usize,
+    glare: Option<Glare>,
+    // Width of the image and the glow at half of its size.
+    width: usize,
+    glow: Option<Plane>,
+}
+
+impl Bloom {
+    pub fn new(
+        threshold: f64,
+        intensity: f64,
+        radius: f64,
+        levels: usize,
+        glare: Option<Glare>,
+    ) -> Self {
+        Self {
+            threshold,
+            intensity,
+            radius,
+            levels: levels.max(1),
+            glare,
+            width: 0,
+            glow: None,
+        }
+    }
+}
+
+impl ImageProcess for Bloom {
+    fn update(&mut self, image: &Image, buffer: &[Color], _settle: bool) -> bool {
+        let bright = Plane::from_fn(image.width, image.height, |x, y| {
+            let color = buffer[y * image.width + x];
+            let l = luminance(&color);
+            if l > self.threshold {
+                color * ((l - self.threshold) / l)
+            } else {
+                Color::default()
+            }
+        })
+        .downsample();
+
+        let mut glow = vec![Color::default(); bright.width * bright.height];
+        let mut level: Option<Plane> = None;
+        for scale in (0..self.levels).map(|i| 2_usize.pow(i as u32)) {
+            let blurred = match &level {
+                None => bright.blur(self.radius),
+                Some(previous) if previous.width > 2 && previous.height > 2 => {
+                    previous.downsample().blur(self.radius)
+                }
+                Some(_) => break,
+            };
+            let intensity = self.intensity / self.levels as f64;
+            glow.par_iter_mut().enumerate().for_each(|(i, glow)| {
+                let x = ((i % bright.width) as f64 + 0.5) / scale as f64 - 0.5;
+                let y = ((i / bright.width) as f64 + 0.5) / scale as f64 - 0.5;
+                *glow += blurred.sample(x, y, true) * intensity;
+            });
+            level = Some(blurred);
+        }
+
+        if let Some(glare) = self.glare.as_ref().filter(|glare| glare.streaks > 0) {
+            let intensity = glare.intensity / glare.streaks as f64;
+            for streak in 0..glare.streaks {
+                let angle = glare.rotation + streak as f64 * 2.0 * PI / glare.streaks as f64;
+                let streak = bright.streak((angle.cos(), angle.sin()), glare.length / 2.0);
+                glow.par_iter_mut()
+                    .zip(streak.data.par_iter())
+                    .for_each(|(glow, streak)| *glow += streak * intensity);
+            }
+        }
+        self.width = image.width;
+        self.glow = Some(Plane {
+            width: bright.width,
+            height: bright.height,
+            data: glow,
+        });
+        true
+    }
+
+    fn process(&self, index: usize, color: &Color) -> Color {
+        match &self.glow {
+            Some(glow) => {
+                let x = ((index % self.width) as f64 + 0.5) / 2.0 - 0.5;
+                let y = ((index / self.width) as f64 + 0.5) / 2.0 - 0.5;
+                *color + glow.sample(x, y, true)
+            }
+            None => *color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bright_parts_glow() {
+        let image = Image::new(128, 128);
+        let mut buffer = vec![Color::new(0.5, 0.5, 0.5); 128 * 128];
+        buffer[16 * 128 + 16] = Color::new(100.0, 100.0, 100.0);
+        let glare = Glare {
+            streaks: 4,
+            length: 8.0,
+            intensity: 0.1,
+            rotation: 0.0,
+        };
+        let mut bloom = Bloom::new(1.0, 0.5, 1.0, 3, Some(glare));
+        bloom.update(&image, &buffer, false);
+
+        let glow = |x: usize, y: usize| {
+            let index = y * 128 + x;
+            bloom.process(index, &buffer[index]) - buffer[index]
+        };
+        assert!(*glow(17, 16).x() > 0.0);
+        assert!(*glow(16, 20).x() > 0.0);
+        assert!(glow(17, 16).x() > glow(20, 20).x());
+        assert_eq!(*glow(127, 127).x(), 0.0);
+
+        let mut dim = Bloom::new(1.0, 0.5, 1.0, 3, None);
+        dim.update(&image, &vec![Color::new(0.5, 0.5, 0.5); 128 * 128], false);
+        assert_eq!(dim.process(0, &buffer[0]), buffer[0]);
+    }
+}
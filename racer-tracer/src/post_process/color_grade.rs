@@ -0,0 +1,40 @@
+use crate::vec3::Color;
+
+use super::{luminance, PostProcess};
+
+// Contrast is applied around middle grey so the overall brightness
+// stays about the same.
+const MIDDLE_GREY: f64 = 0.18;
+
+pub struct ColorGrade {
+    contrast: f64,
+    saturation: f64,
+}
+
+impl ColorGrade {
+    pub fn new(contrast: f64, saturation: f64) -> Self {
+        Self {
+            contrast,
+            saturation,
+        }
+    }
+}
+
+impl PostProcess for ColorGrade {
+    fn process(&self, color: &Color) -> Color {
+        let contrast = |c: f64| MIDDLE_GREY * (c.max(0.0) / MIDDLE_GREY).powf(self.contrast);
+        let color = Color::new(
+            contrast(*color.x()),
+            contrast(*color.y()),
+            contrast(*color.z()),
+        );
+
+        let grey = luminance(&color);
+        let saturate = |c: f64| (grey + (c - grey) * self.saturation).max(0.0);
+        Color::new(
+            saturate(*color.x()),
+            saturate(*color.y()),
+            saturate(*color.z()),
+        )
+    }
+}
@@ -0,0 +1,22 @@
+use crate::vec3::Color;
+
+use super::PostProcess;
+
+// Scales the image by 2^ev, like opening up a camera by ev stops.
+pub struct Exposure {
+    scale: f64,
+}
+
+impl Exposure {
+    pub fn new(ev: f64) -> Self {
+        Self {
+            scale: 2.0_f64.powf(ev),
+        }
+    }
+}
+
+impl PostProcess for Exposure {
+    fn process(&self, color: &Color) -> Color {
+        color * self.scale
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fs, path::Path};
+
+use crate::{error::TracerError, vec3::Color};
+
+use super::PostProcess;
+
+// 3D lookup table read from an Adobe/Resolve .cube file. Colors
+// between the entries are interpolated.
+pub struct Lut {
+    size: usize,
+    domain_min: Color,
+    domain_max: Color,
+    // Red changes fastest, then green and then blue.
+    table: Vec<Color>,
+}
+
+fn parse_color(values: &[&str]) -> Option<Color> {
+    match values {
+        [r, g, b] => Some(Color::new(
+            r.parse().ok()?,
+            g.parse().ok()?,
+            b.parse().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+impl Lut {
+    pub fn load(path: &Path) -> Result<Self, TracerError> {
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Self::parse(&source))
+            .map_err(|e| TracerError::PostProcess(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut domain_min = Color::new(0.0, 0.0, 0.0);
+        let mut domain_max = Color::new(1.0, 1.0, 1.0);
+        let mut table = Vec::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+
+            let values = line.split_whitespace().collect::<Vec<&str>>();
+            let error = || format!("Bad line {}: {}", number + 1, line);
+            match values[0] {
+                "LUT_3D_SIZE" => {
+                    size = Some(
+                        values
+                            .get(1)
+                            .and_then(|s| s.parse::<usize>().ok())
+                            .filter(|s| *s >= 2)
+                            .ok_or_else(error)?,
+                    )
+                }
+                "LUT_1D_SIZE" => return Err(String::from("1D LUTs are not supported")),
+                "DOMAIN_MIN" => domain_min = parse_color(&values[1..]).ok_or_else(error)?,
+                "DOMAIN_MAX" => domain_max = parse_color(&values[1..]).ok_or_else(error)?,
+                _ => table.push(parse_color(&values).ok_or_else(error)?),
+            }
+        }
+
+        let size = size.ok_or_else(|| String::from("Missing LUT_3D_SIZE"))?;
+        if table.len() != size * size * size {
+            return Err(format!(
+                "Expected {} entries but found {}",
+                size * size * size,
+                table.len()
+            ));
+        }
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> Color {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+}
+
+impl PostProcess for Lut {
+    fn process(&self, color: &Color) -> Color {
+        // Position in the table and how far towards the next entry.
+        let axis = |i: usize| {
+            let range = self.domain_max[i] - self.domain_min[i];
+            let p =
+                ((color[i] - self.domain_min[i]) / range).clamp(0.0, 1.0) * (self.size - 1) as f64;
+            let low = (p.floor() as usize).min(self.size - 2);
+            (low, p - low as f64)
+        };
+        let ((r, fr), (g, fg), (b, fb)) = (axis(0), axis(1), axis(2));
+
+        let lerp = |a: Color, b: Color, t: f64| a + (b - a) * t;
+        let plane = |b: usize| {
+            lerp(
+                lerp(self.entry(r, g, b), self.entry(r + 1, g, b), fr),
+                lerp(self.entry(r, g + 1, b), self.entry(r + 1, g + 1, b), fr),
+                fg,
+            )
+        };
+        lerp(plane(b), plane(b + 1), fb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates() {
+        // Swaps red and blue and halves green.
+        let source = "TITLE \"swap\"\n# comment\nLUT_3D_SIZE 2\n\n\
+                      0 0 0\n0 0 1\n0 0.5 0\n0 0.5 1\n\
+                      1 0 0\n1 0 1\n1 0.5 0\n1 0.5 1\n";
+        let lut = Lut::parse(source).unwrap();
+        let color = lut.process(&Color::new(0.25, 0.5, 1.0));
+        assert!((color - Color::new(1.0, 0.25, 0.25)).length() < 1e-9);
+        // Outside of the domain is clamped.
+        let color = lut.process(&Color::new(2.0, -1.0, 0.0));
+        assert!((color - Color::new(0.0, 0.0, 1.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(Lut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut::parse("0 0 0\n").is_err());
+        assert!(Lut::parse("LUT_3D_SIZE 2\n0 0 x\n").is_err());
+        assert!(Lut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    }
+}
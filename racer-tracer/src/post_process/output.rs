@@ -0,0 +1,80 @@
+use crate::{
+    config::OutputTransformConfig,
+    vec3::{Color, Vec3},
+};
+
+use super::{mul, PostProcess};
+
+// Linear sRGB to linear Display P3. Both use the D65 white.
+const SRGB_TO_P3: [Vec3; 3] = [
+    Vec3::new(0.8224621, 0.1775380, 0.0),
+    Vec3::new(0.0331941, 0.9668058, 0.0),
+    Vec3::new(0.0170827, 0.0723974, 0.9105199),
+];
+
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn linear_to_rec709(c: f64) -> f64 {
+    if c < 0.018 {
+        c * 4.5
+    } else {
+        1.099 * c.powf(0.45) - 0.099
+    }
+}
+
+// Encodes the linear image for the display. Always the last stage
+// since everything before it works on linear colors.
+pub struct OutputTransform {
+    transform: OutputTransformConfig,
+}
+
+impl OutputTransform {
+    pub fn new(transform: &OutputTransformConfig) -> Self {
+        Self {
+            transform: transform.clone(),
+        }
+    }
+}
+
+impl PostProcess for OutputTransform {
+    fn process(&self, color: &Color) -> Color {
+        let (color, encode): (Color, fn(f64) -> f64) = match self.transform {
+            OutputTransformConfig::Srgb => (*color, linear_to_srgb),
+            OutputTransformConfig::Rec709 => (*color, linear_to_rec709),
+            OutputTransformConfig::DisplayP3 => (mul(&SRGB_TO_P3, color), linear_to_srgb),
+        };
+        Color::new(
+            encode(color.x().clamp(0.0, 1.0)),
+            encode(color.y().clamp(0.0, 1.0)),
+            encode(color.z().clamp(0.0, 1.0)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for c in [0.0, 0.002, 0.04045, 0.2, 0.5, 1.0] {
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-12);
+        }
+        // Middle grey ends up a bit below half.
+        assert!((linear_to_srgb(0.18) - 0.4613).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::vec3::{Color, Vec3};
+
+use super::{mul, mul_matrix, PostProcess};
+
+// How far one step of tint moves the white point along y.
+const TINT_SCALE: f64 = 0.0002;
+
+const RGB_TO_XYZ: [Vec3; 3] = [
+    Vec3::new(0.4124564, 0.3575761, 0.1804375),
+    Vec3::new(0.2126729, 0.7151522, 0.0721750),
+    Vec3::new(0.0193339, 0.1191920, 0.9503041),
+];
+
+const XYZ_TO_RGB: [Vec3; 3] = [
+    Vec3::new(3.2404542, -1.5371385, -0.4985314),
+    Vec3::new(-0.9692660, 1.8760108, 0.0415560),
+    Vec3::new(0.0556434, -0.2040259, 1.0572252),
+];
+
+const BRADFORD: [Vec3; 3] = [
+    Vec3::new(0.8951, 0.2664, -0.1614),
+    Vec3::new(-0.7502, 1.7135, 0.0367),
+    Vec3::new(0.0389, -0.0685, 1.0296),
+];
+
+const BRADFORD_INVERSE: [Vec3; 3] = [
+    Vec3::new(0.9869929, -0.1470543, 0.1599627),
+    Vec3::new(0.4323053, 0.5183603, 0.0492912),
+    Vec3::new(-0.0085287, 0.0400428, 0.9684867),
+];
+
+// The white of the sRGB colors the renderer works in.
+const D65: Vec3 = Vec3::new(0.95047, 1.0, 1.08883);
+
+// CIE xy of a light with the color temperature in kelvin. Daylight
+// from 4000K and up, a black body below that.
+fn temperature_xy(temperature: f64) -> (f64, f64) {
+    let t = temperature.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    if t >= 4000.0 {
+        let x = if t <= 7000.0 {
+            -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+        } else {
+            -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+        };
+        (x, -3.0 * x * x + 2.870 * x - 0.275)
+    } else {
+        let x = -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910;
+        let y = if t <= 2222.0 {
+            -1.1063814 * x * x * x - 1.34811020 * x * x + 2.18555832 * x - 0.20219683
+        } else {
+            -0.9549476 * x * x * x - 1.37418593 * x * x + 2.09137015 * x - 0.16748867
+        };
+        (x, y)
+    }
+}
+
+// Makes light of the given temperature and tint white. Like the white
+// balance of a camera, a low temperature cancels out warm light.
+// Positive tint cancels out green.
+pub struct WhiteBalance {
+    matrix: [Vec3; 3],
+}
+
+impl WhiteBalance {
+    pub fn new(temperature: f64, tint: f64) -> Self {
+        let (x, y) = temperature_xy(temperature);
+        let y = y + tint * TINT_SCALE;
+        let source = Vec3::new(x / y, 1.0, (1.0 - x - y) / y);
+
+        let source_lms = mul(&BRADFORD, &source);
+        let target_lms = mul(&BRADFORD, &D65);
+        let scale = [
+            Vec3::new(target_lms.x() / source_lms.x(), 0.0, 0.0),
+            Vec3::new(0.0, target_lms.y() / source_lms.y(), 0.0),
+            Vec3::new(0.0, 0.0, target_lms.z() / source_lms.z()),
+        ];
+
+        let to_lms = mul_matrix(&BRADFORD, &RGB_TO_XYZ);
+        let from_lms = mul_matrix(&XYZ_TO_RGB, &BRADFORD_INVERSE);
+        Self {
+            matrix: mul_matrix(&from_lms, &mul_matrix(&scale, &to_lms)),
+        }
+    }
+}
+
+impl PostProcess for WhiteBalance {
+    fn process(&self, color: &Color) -> Color {
+        mul(&self.matrix, color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_grey(color: &Color) {
+        assert!(
+            (color.x() - color.y()).abs() < 0.01 && (color.z() - color.y()).abs() < 0.01,
+            "Expected grey, got {:?}",
+            color
+        );
+    }
+
+    #[test]
+    fn daylight_is_left_alone() {
+        let balance = WhiteBalance::new(6504.0, 0.0);
+        let color = Color::new(0.2, 0.5, 0.8);
+        let balanced = balance.process(&color);
+        assert!((balanced - color).length() < 0.01, "{:?}", balanced);
+    }
+
+    #[test]
+    fn cancels_out_the_light() {
+        for temperature in [2700.0, 3200.0, 5000.0, 9000.0] {
+            let (x, y) = temperature_xy(temperature);
+            let light = mul(&XYZ_TO_RGB, &Vec3::new(x / y, 1.0, (1.0 - x - y) / y));
+            assert_grey(&WhiteBalance::new(temperature, 0.0).process(&light));
+        }
+
+        let (x, y) = temperature_xy(6504.0);
+        let y = y + 20.0 * TINT_SCALE;
+        let green = mul(&XYZ_TO_RGB, &Vec3::new(x / y, 1.0, (1.0 - x - y) / y));
+        assert!(green.y() > green.x());
+        assert_grey(&WhiteBalance::new(6504.0, 20.0).process(&green));
+    }
+}
@@ -1,5 +1,13 @@
-use std::time::Duration;
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
+use slog::Logger;
 use synchronoise::SignalEvent;
 
 use crate::{
@@ -9,18 +17,26 @@ use crate::{
     data_bus::DataWriter,
     error::TracerError,
     geometry::Hittable,
-    image::Image,
+    image::{Image, Region},
     image_buffer::ImageBufferEvent,
     ray::Ray,
+    scene::PlacedObject,
     vec3::{Color, Vec3},
 };
 
-use self::{cpu::CpuRenderer, cpu_scaled::CpuRendererScaled};
+use self::{
+    checkpoint::Checkpoint, cpu::CpuRenderer, cpu_scaled::CpuRendererScaled,
+    distributed::DistributedRenderer,
+};
 
+pub mod checkpoint;
 pub mod cpu;
 pub mod cpu_scaled;
 pub mod denoised;
+pub mod distributed;
 pub mod image;
+pub mod report;
+pub mod tiles;
 
 fn do_cancel(cancel_event: Option<&SignalEvent>) -> bool {
     match cancel_event {
@@ -38,6 +54,24 @@ pub struct RayImageData {
     obj_id: usize,
 }
 
+thread_local! {
+    // Counted per thread and moved to the progress every row so the
+    // render threads don't fight over a shared counter for every ray.
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+    static NODE_VISITS: Cell<u64> = const { Cell::new(0) };
+}
+
+pub fn count_node_visit() {
+    NODE_VISITS.with(|visits| visits.set(visits.get() + 1));
+}
+
+fn take_ray_counts() -> (u64, u64) {
+    (
+        RAYS.with(|rays| rays.take()),
+        NODE_VISITS.with(|visits| visits.take()),
+    )
+}
+
 fn ray_color(
     scene: &dyn Hittable,
     ray: &Ray,
@@ -55,7 +89,8 @@ fn ray_color(
         };
     }
 
-    match scene.hit(ray, 0.001, std::f64::INFINITY) {
+    RAYS.with(|rays| rays.set(rays.get() + 1));
+    match scene.hit(ray, 0.001, f64::INFINITY) {
         Some(rec) => {
             let emitted = rec.material.color_emitted(rec.u, rec.v, &rec.point);
             let color = rec
@@ -89,6 +124,214 @@ fn ray_color(
     }
 }
 
+// Keeps track of how far along the current render is. Counted in
+// samples where every pixel needs `samples` of them to be done.
+pub struct RenderProgress {
+    final_render: AtomicBool,
+    rendering: AtomicBool,
+    samples: AtomicU64,
+    pixels: AtomicU64,
+    done: AtomicU64,
+    // Samples picked up from a checkpoint, part of done.
+    resumed: AtomicU64,
+    rays: AtomicU64,
+    node_visits: AtomicU64,
+    tile_count: AtomicU64,
+    start: Mutex<Instant>,
+    // How long each finished tile took to render.
+    tiles: Mutex<Vec<(Region, Duration)>>,
+}
+
+#[derive(Clone)]
+pub struct ProgressInfo {
+    pub final_render: bool,
+    pub rendering: bool,
+    pub samples: u64,
+    pub pixels: u64,
+    pub done: u64,
+    pub resumed: u64,
+    pub rays: u64,
+    pub node_visits: u64,
+    pub tiles: u64,
+    pub tiles_done: u64,
+    pub elapsed: Duration,
+}
+
+impl ProgressInfo {
+    // Samples done per pixel.
+    pub fn samples_done(&self) -> f64 {
+        if self.pixels == 0 {
+            0.0
+        } else {
+            self.done as f64 / self.pixels as f64
+        }
+    }
+
+    pub fn fraction(&self) -> f64 {
+        let total = self.samples * self.pixels;
+        if total == 0 {
+            0.0
+        } else {
+            (self.done as f64 / total as f64).min(1.0)
+        }
+    }
+
+    // Based on the samples taken since the render started, resumed
+    // ones came for free.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.samples * self.pixels;
+        let traced = self.done.saturating_sub(self.resumed);
+        (self.rendering && traced > 0).then(|| {
+            self.elapsed
+                .mul_f64(total.saturating_sub(self.done) as f64 / traced as f64)
+        })
+    }
+
+    // Rays per pixel for the samples taken since the render started.
+    pub fn rays_per_pixel(&self) -> f64 {
+        let traced = self.done.saturating_sub(self.resumed);
+        if traced == 0 {
+            0.0
+        } else {
+            self.rays as f64 * self.samples as f64 / traced as f64
+        }
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.rays as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let per = |count: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        format!(
+            "Rendered in {:.1} seconds. {} rays, {:.1} rays per pixel, {:.1} BVH node visits per ray, {:.2} Mrays/s.",
+            self.elapsed.as_secs_f64(),
+            self.rays,
+            self.rays_per_pixel(),
+            per(self.node_visits, self.rays),
+            self.rays_per_second() / 1_000_000.0
+        )
+    }
+}
+
+impl RenderProgress {
+    pub fn new() -> Self {
+        Self {
+            final_render: AtomicBool::new(false),
+            rendering: AtomicBool::new(false),
+            samples: AtomicU64::new(0),
+            pixels: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            resumed: AtomicU64::new(0),
+            rays: AtomicU64::new(0),
+            node_visits: AtomicU64::new(0),
+            tile_count: AtomicU64::new(0),
+            start: Mutex::new(Instant::now()),
+            tiles: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn begin(&self, final_render: bool, samples: usize, pixels: usize) {
+        if let Ok(mut start) = self.start.lock() {
+            *start = Instant::now();
+        }
+        if let Ok(mut tiles) = self.tiles.lock() {
+            tiles.clear();
+        }
+        self.final_render.store(final_render, Ordering::Relaxed);
+        self.samples.store(samples as u64, Ordering::Relaxed);
+        self.pixels.store(pixels as u64, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+        self.resumed.store(0, Ordering::Relaxed);
+        self.rays.store(0, Ordering::Relaxed);
+        self.node_visits.store(0, Ordering::Relaxed);
+        self.tile_count.store(0, Ordering::Relaxed);
+        self.rendering.store(true, Ordering::Relaxed);
+    }
+
+    // Also picks up the rays traced on the calling thread.
+    pub fn add(&self, samples: usize) {
+        self.done.fetch_add(samples as u64, Ordering::Relaxed);
+        let (rays, node_visits) = take_ray_counts();
+        self.add_rays(rays, node_visits);
+    }
+
+    // Samples a resumed render already has.
+    pub fn resume(&self, samples: u64) {
+        self.done.fetch_add(samples, Ordering::Relaxed);
+        self.resumed.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    pub fn add_rays(&self, rays: u64, node_visits: u64) {
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+        self.node_visits.fetch_add(node_visits, Ordering::Relaxed);
+    }
+
+    // Rays counted since the last time they were taken.
+    pub fn take_rays(&self) -> (u64, u64) {
+        (
+            self.rays.swap(0, Ordering::Relaxed),
+            self.node_visits.swap(0, Ordering::Relaxed),
+        )
+    }
+
+    pub fn set_tile_count(&self, tiles: usize) {
+        self.tile_count.store(tiles as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_tile_time(&self, tile: &Region, duration: Duration) {
+        if let Ok(mut tiles) = self.tiles.lock() {
+            tiles.push((*tile, duration));
+        }
+    }
+
+    pub fn tile_times(&self) -> Vec<(Region, Duration)> {
+        self.tiles
+            .lock()
+            .map(|tiles| tiles.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn finish(&self) {
+        self.rendering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn info(&self) -> ProgressInfo {
+        ProgressInfo {
+            final_render: self.final_render.load(Ordering::Relaxed),
+            rendering: self.rendering.load(Ordering::Relaxed),
+            samples: self.samples.load(Ordering::Relaxed),
+            pixels: self.pixels.load(Ordering::Relaxed),
+            done: self.done.load(Ordering::Relaxed),
+            resumed: self.resumed.load(Ordering::Relaxed),
+            rays: self.rays.load(Ordering::Relaxed),
+            node_visits: self.node_visits.load(Ordering::Relaxed),
+            tiles: self.tile_count.load(Ordering::Relaxed),
+            tiles_done: self
+                .tiles
+                .lock()
+                .map(|tiles| tiles.len() as u64)
+                .unwrap_or_default(),
+            elapsed: self
+                .start
+                .lock()
+                .map(|start| start.elapsed())
+                .unwrap_or_default(),
+        }
+    }
+}
+
 pub struct RenderData<'a> {
     pub camera_data: &'a CameraSharedData,
     pub image: &'a Image,
@@ -96,6 +339,14 @@ pub struct RenderData<'a> {
     pub background: &'a dyn BackgroundColor,
     pub config: &'a Config,
     pub cancel_event: Option<&'a SignalEvent>,
+    pub progress: &'a RenderProgress,
+    // Only render this part of the image when set.
+    pub region: Option<Region>,
+    // Samples are accumulated here so the render can be resumed.
+    pub checkpoint: Option<&'a Checkpoint>,
+    // Where the objects are now when they have been edited since the
+    // scene was loaded.
+    pub objects: Option<&'a [PlacedObject]>,
 }
 
 pub trait Renderer: Send + Sync {
@@ -106,11 +357,17 @@ pub trait Renderer: Send + Sync {
     ) -> Result<(), TracerError>;
 }
 
-impl From<(&RendererConfig, &RenderConfig, &Image)> for Box<dyn Renderer> {
-    fn from(r: (&RendererConfig, &RenderConfig, &Image)) -> Self {
+impl From<(&RendererConfig, &RenderConfig, &Logger)> for Box<dyn Renderer> {
+    fn from(r: (&RendererConfig, &RenderConfig, &Logger)) -> Self {
         match r.0 {
             RendererConfig::Cpu => Box::new(CpuRenderer::new(r.1.clone())),
-            RendererConfig::CpuPreview => Box::new(CpuRendererScaled::new(r.1.clone(), r.2)),
+            RendererConfig::CpuPreview => Box::new(CpuRendererScaled::new(r.1.clone())),
+            RendererConfig::Distributed { workers, timeout } => Box::new(DistributedRenderer::new(
+                r.1.clone(),
+                workers.clone(),
+                *timeout,
+                r.2.new(o!("scope" => "distributed")),
+            )),
         }
     }
 }
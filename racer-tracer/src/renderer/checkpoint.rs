@@ -0,0 +1,324 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+use slog::Logger;
+
+use crate::{
+    camera::CameraState,
+    config::{Config, SceneLoaderConfig},
+    error::TracerError,
+    image::{Image, Region},
+    scene::Scene,
+    vec3::Color,
+};
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 1;
+
+// Identifies what is being rendered. Anything that changes the image
+// has to be part of it. The number of samples is left out since the
+// samples per pixel are stored in the checkpoint and a render can be
+// continued with more of them.
+pub fn checkpoint_key(config: &Config, camera: &CameraState, scene: &Scene) -> String {
+    let mut sha = Sha256::new();
+    match &config.loader {
+        SceneLoaderConfig::Yml { path } => sha.update(fs::read(path).unwrap_or_default()),
+        SceneLoaderConfig::Random => sha.update(format!("{:?}", config.scene_seed)),
+        _ => (),
+    }
+    sha.update(format!("{:?}", config.loader));
+    sha.update(format!("{:?}", config.renderer));
+    sha.update(format!("{:?}", config.camera));
+    sha.update(format!("{:?}", camera));
+    sha.update(format!(
+        "{}x{} {}",
+        config.screen.width, config.screen.height, config.render.max_depth
+    ));
+
+    // Objects might have been moved or removed since the scene was
+    // loaded.
+    scene
+        .objects()
+        .iter()
+        .for_each(|object| sha.update(format!("{:?}", object.pos())));
+    format!("{:X}", sha.finalize())
+}
+
+// Sums of all samples taken so far together with how many there
+// are for every pixel.
+#[derive(Clone)]
+struct Accumulation {
+    sums: Vec<Color>,
+    counts: Vec<u32>,
+}
+
+impl Accumulation {
+    fn new(size: usize) -> Self {
+        Self {
+            sums: vec![Color::default(); size],
+            counts: vec![0; size],
+        }
+    }
+
+    fn write(&self, width: usize, height: usize, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(width as u32).to_le_bytes())?;
+        writer.write_all(&(height as u32).to_le_bytes())?;
+        self.sums
+            .iter()
+            .zip(self.counts.iter())
+            .try_for_each(|(sum, count)| {
+                writer.write_all(&sum.x().to_le_bytes())?;
+                writer.write_all(&sum.y().to_le_bytes())?;
+                writer.write_all(&sum.z().to_le_bytes())?;
+                writer.write_all(&count.to_le_bytes())
+            })
+    }
+
+    fn read(width: usize, height: usize, reader: &mut impl Read) -> Result<Self, String> {
+        let mut u32_bytes = [0; 4];
+        let mut f64_bytes = [0; 8];
+        let mut read_u32 = |reader: &mut dyn Read| {
+            reader
+                .read_exact(&mut u32_bytes)
+                .map(|_| u32::from_le_bytes(u32_bytes))
+                .map_err(|e| e.to_string())
+        };
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if &magic != MAGIC {
+            return Err("Not a checkpoint file".to_string());
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(format!("Unsupported version {}", version));
+        }
+        let size = (read_u32(reader)? as usize, read_u32(reader)? as usize);
+        if size != (width, height) {
+            return Err(format!(
+                "Image size {}x{} does not match {}x{}",
+                size.0, size.1, width, height
+            ));
+        }
+
+        let mut accumulation = Accumulation::new(width * height);
+        for (sum, count) in accumulation
+            .sums
+            .iter_mut()
+            .zip(accumulation.counts.iter_mut())
+        {
+            for channel in 0..3 {
+                reader
+                    .read_exact(&mut f64_bytes)
+                    .map_err(|e| e.to_string())?;
+                sum[channel] = f64::from_le_bytes(f64_bytes);
+            }
+            *count = read_u32(reader)?;
+        }
+        Ok(accumulation)
+    }
+}
+
+// Keeps the samples of a final render so it can be continued if it
+// gets interrupted. The samples are written to disk every now and
+// then while rendering.
+pub struct Checkpoint {
+    path: PathBuf,
+    image: Image,
+    interval: Duration,
+    last_save: Mutex<Instant>,
+    accumulation: Mutex<Accumulation>,
+    log: Logger,
+}
+
+impl Checkpoint {
+    // Picks up the samples from an earlier checkpoint with the same
+    // key if resume is set.
+    pub fn new(
+        log: Logger,
+        dir: &Path,
+        key: &str,
+        image: &Image,
+        resume: bool,
+        interval: Duration,
+    ) -> Self {
+        let path = dir.join(format!("{}.checkpoint", key));
+        let accumulation = resume
+            .then(|| {
+                File::open(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|file| {
+                        Accumulation::read(image.width, image.height, &mut BufReader::new(file))
+                    })
+                    .map_err(|e| info!(log, "No checkpoint to resume from: {}", e))
+                    .ok()
+            })
+            .flatten()
+            .map(|accumulation| {
+                info!(
+                    log,
+                    "Resuming from {} with {} samples done.",
+                    path.display(),
+                    accumulation.counts.iter().map(|c| *c as u64).sum::<u64>()
+                );
+                accumulation
+            })
+            .unwrap_or_else(|| Accumulation::new(image.width * image.height));
+
+        Self {
+            path,
+            image: image.clone(),
+            interval,
+            last_save: Mutex::new(Instant::now()),
+            accumulation: Mutex::new(accumulation),
+            log,
+        }
+    }
+
+    fn accumulation(&self) -> Result<MutexGuard<'_, Accumulation>, TracerError> {
+        self.accumulation
+            .lock()
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("checkpoint: {}", e)))
+    }
+
+    // Sums and sample counts of the pixels in the tile.
+    pub fn tile(&self, tile: &Region) -> Result<(Vec<Color>, Vec<u32>), TracerError> {
+        self.accumulation().map(|accumulation| {
+            (tile.y..tile.y + tile.height)
+                .flat_map(|row| {
+                    let start = row * self.image.width + tile.x;
+                    (start..start + tile.width)
+                        .map(|index| (accumulation.sums[index], accumulation.counts[index]))
+                        .collect::<Vec<(Color, u32)>>()
+                })
+                .unzip()
+        })
+    }
+
+    // Samples already taken in the region. Pixels count at most
+    // samples each since that is all the render needs.
+    pub fn samples_done(&self, region: &Region, samples: usize) -> Result<u64, TracerError> {
+        self.accumulation().map(|accumulation| {
+            (region.y..region.y + region.height)
+                .flat_map(|row| {
+                    let start = row * self.image.width + region.x;
+                    accumulation.counts[start..start + region.width].iter()
+                })
+                .map(|count| (*count as u64).min(samples as u64))
+                .sum()
+        })
+    }
+
+    pub fn add_tile(
+        &self,
+        tile: &Region,
+        sums: &[Color],
+        counts: &[u32],
+    ) -> Result<(), TracerError> {
+        self.accumulation().map(|mut accumulation| {
+            for row in 0..tile.height {
+                let start = (tile.y + row) * self.image.width + tile.x;
+                let tile_start = row * tile.width;
+                accumulation.sums[start..start + tile.width]
+                    .copy_from_slice(&sums[tile_start..tile_start + tile.width]);
+                accumulation.counts[start..start + tile.width]
+                    .copy_from_slice(&counts[tile_start..tile_start + tile.width]);
+            }
+        })?;
+
+        let due = self
+            .last_save
+            .lock()
+            .map(|mut last_save| {
+                let due = last_save.elapsed() >= self.interval;
+                if due {
+                    *last_save = Instant::now();
+                }
+                due
+            })
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("checkpoint: {}", e)))?;
+
+        if due {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    // Failing to write the checkpoint shouldn't stop the render so
+    // it's only logged.
+    pub fn save(&self) -> Result<(), TracerError> {
+        // Copied so the renderer isn't blocked while writing.
+        let accumulation = self.accumulation()?.clone();
+
+        // Written next to the checkpoint first so a crash while
+        // writing doesn't ruin the previous one.
+        let tmp_path = self.path.with_extension("tmp");
+        let res = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(&tmp_path))
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                accumulation
+                    .write(self.image.width, self.image.height, &mut writer)
+                    .and_then(|_| writer.flush())
+            })
+            .and_then(|_| fs::rename(&tmp_path, &self.path));
+
+        match res {
+            Ok(_) => info!(self.log, "Saved checkpoint to {}", self.path.display()),
+            Err(e) => warn!(
+                self.log,
+                "Failed to save checkpoint {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+        Ok(())
+    }
+
+    // Nothing left to resume once the render is done.
+    pub fn remove(&self) {
+        if self.path.exists() {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!(
+                    self.log,
+                    "Failed to remove checkpoint {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let mut accumulation = Accumulation::new(6);
+        accumulation.sums[4] = Color::new(1.5, 2.0, 0.25);
+        accumulation.counts[4] = 17;
+
+        let mut bytes = Vec::new();
+        accumulation.write(3, 2, &mut bytes).unwrap();
+        let read = Accumulation::read(3, 2, &mut bytes.as_slice()).unwrap();
+        assert_eq!(read.sums, accumulation.sums);
+        assert_eq!(read.counts, accumulation.counts);
+
+        assert!(Accumulation::read(2, 3, &mut bytes.as_slice()).is_err());
+        assert!(Accumulation::read(3, 2, &mut &bytes[..20]).is_err());
+    }
+}
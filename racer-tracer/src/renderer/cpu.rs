@@ -1,19 +1,20 @@
-use rayon::prelude::*;
-
 use crate::{
     camera::{Camera, CameraSharedData},
     config::RenderConfig,
     data_bus::DataWriter,
     error::TracerError,
-    image::SubImage,
+    image::{Region, SubImage},
     image_buffer::ImageBufferEvent,
-    renderer::{do_cancel, ray_color, Renderer},
+    renderer::{do_cancel, ray_color, tiles::render_tiles, Renderer},
     util::random_double,
     vec3::{Color, Vec3},
 };
 
 use super::RenderData;
 
+// Colors and object ids of the pixels in a tile.
+pub type TracedTile = (Vec<Color>, Vec<usize>);
+
 pub struct CpuRenderer {
     config: RenderConfig,
 }
@@ -23,96 +24,104 @@ impl CpuRenderer {
         Self { config }
     }
 
-    pub fn raytrace(
+    // None if the render got cancelled.
+    pub fn trace(
         &self,
         rd: &RenderData,
         camera_data: &CameraSharedData,
-        image: SubImage<ImageBufferEvent>,
-    ) -> Result<(), TracerError> {
-        let mut buffer = vec![Vec3::default(); image.height * image.width];
-        for row in 0..image.height {
-            for column in 0..image.width {
+        tile: &Region,
+    ) -> Result<Option<TracedTile>, TracerError> {
+        // Pixels continue from the samples in the checkpoint.
+        let (mut sums, mut counts) = match rd.checkpoint {
+            Some(checkpoint) => checkpoint.tile(tile)?,
+            None => (vec![Color::default(); tile.area()], vec![0; tile.area()]),
+        };
+        let mut buffer = vec![Vec3::default(); tile.area()];
+        let mut obj_id = vec![0; tile.area()];
+        for row in 0..tile.height {
+            let mut added = 0;
+            for column in 0..tile.width {
+                let index = row * tile.width + column;
+                let taken = counts[index] as usize;
+                added += self.config.samples.saturating_sub(taken);
                 let u: f64 =
-                    ((image.x + column) as f64 + random_double()) / (image.screen_width - 1) as f64;
-                let mut color = Color::default();
-                for _ in 0..rd.config.render.samples {
-                    let v: f64 = ((image.y + row) as f64 + random_double())
-                        / (image.screen_height - 1) as f64;
-                    color.add(
-                        ray_color(
-                            rd.scene,
-                            &Camera::get_ray(camera_data, u, v),
-                            rd.background,
-                            rd.config.render.max_depth,
-                            &camera_data.origin,
-                        )
-                        .rgb,
+                    ((tile.x + column) as f64 + random_double()) / (rd.image.width - 1) as f64;
+                let mut color = sums[index];
+                for sample in taken..self.config.samples {
+                    let v: f64 =
+                        ((tile.y + row) as f64 + random_double()) / (rd.image.height - 1) as f64;
+                    let data = ray_color(
+                        rd.scene,
+                        &Camera::get_ray(camera_data, u, v),
+                        rd.background,
+                        rd.config.render.max_depth,
+                        &camera_data.origin,
                     );
+                    if sample == taken {
+                        obj_id[index] = data.obj_id;
+                    }
+                    color.add(data.rgb);
                 }
-                buffer[row * image.width + column] = color.scale_sqrt(self.config.samples);
+                let total = taken.max(self.config.samples);
+                sums[index] = color;
+                counts[index] = total as u32;
+                buffer[index] = color.average(total);
             }
+            rd.progress.add(added);
 
             if do_cancel(rd.cancel_event) {
-                return Ok(());
+                return Ok(None);
             }
         }
 
-        if do_cancel(rd.cancel_event) {
-            return Ok(());
+        if let Some(checkpoint) = rd.checkpoint {
+            checkpoint.add_tile(tile, &sums, &counts)?;
         }
+        Ok(Some((buffer, obj_id)))
+    }
 
-        image.writer.write(ImageBufferEvent::BufferUpdate {
-            rgb: buffer,
-            r: image.y,
-            c: image.x,
+    pub fn raytrace(
+        &self,
+        rd: &RenderData,
+        camera_data: &CameraSharedData,
+        image: SubImage<ImageBufferEvent>,
+    ) -> Result<(), TracerError> {
+        let tile = Region {
+            x: image.x,
+            y: image.y,
             width: image.width,
             height: image.height,
-        })
+        };
+        match self.trace(rd, camera_data, &tile)? {
+            Some((rgb, obj_id)) => write_tile(&image.writer, &tile, rgb, obj_id),
+            None => Ok(()),
+        }
     }
+}
 
-    pub fn prepare_threads<T: Clone>(
-        rd: &RenderData,
-        conf: &RenderConfig,
-        writer: &DataWriter<T>,
-    ) -> Result<Vec<SubImage<T>>, TracerError> {
-        let width_step = rd.image.width / conf.num_threads_width;
-        let height_step = rd.image.height / conf.num_threads_height;
-
-        (!do_cancel(rd.cancel_event))
-            .then_some(|| ())
-            .ok_or(TracerError::CancelEvent)
-            .map(|_| {
-                (0..conf.num_threads_width)
-                    .flat_map(|ws| {
-                        (0..conf.num_threads_height)
-                            .map(|hs| SubImage {
-                                writer: writer.clone(),
-                                x: width_step * ws,
-                                y: height_step * hs,
-                                screen_width: rd.image.width,
-                                screen_height: rd.image.height,
-
-                                // Neccesary in case the threads width is not
-                                // evenly divisible by the image width.
-                                width: if ws == conf.num_threads_width - 1 {
-                                    rd.image.width - width_step * ws
-                                } else {
-                                    width_step
-                                },
-
-                                // Neccesary in case the threads height is not
-                                // evenly divisible by the image height.
-                                height: if hs == conf.num_threads_height - 1 {
-                                    rd.image.height - height_step * hs
-                                } else {
-                                    height_step
-                                },
-                            })
-                            .collect::<Vec<SubImage<T>>>()
-                    })
-                    .collect::<Vec<SubImage<T>>>()
+pub fn write_tile(
+    writer: &DataWriter<ImageBufferEvent>,
+    tile: &Region,
+    rgb: Vec<Color>,
+    obj_id: Vec<usize>,
+) -> Result<(), TracerError> {
+    writer
+        .write(ImageBufferEvent::ObjectIdUpdate {
+            obj_id,
+            r: tile.y,
+            c: tile.x,
+            width: tile.width,
+            height: tile.height,
+        })
+        .and_then(|_| {
+            writer.write(ImageBufferEvent::BufferUpdate {
+                rgb,
+                r: tile.y,
+                c: tile.x,
+                width: tile.width,
+                height: tile.height,
             })
-    }
+        })
 }
 
 impl Renderer for CpuRenderer {
@@ -121,11 +130,126 @@ impl Renderer for CpuRenderer {
         rd: RenderData,
         writer: &DataWriter<ImageBufferEvent>,
     ) -> Result<(), TracerError> {
-        CpuRenderer::prepare_threads(&rd, &self.config, writer).and_then(|images| {
-            images
-                .into_par_iter()
-                .map(|image| self.raytrace(&rd, rd.camera_data, image))
-                .collect::<Result<(), TracerError>>()
+        // Progress picks up where the checkpoint left off.
+        if let Some(checkpoint) = rd.checkpoint {
+            let region = rd.region.unwrap_or(Region {
+                x: 0,
+                y: 0,
+                width: rd.image.width,
+                height: rd.image.height,
+            });
+            rd.progress
+                .resume(checkpoint.samples_done(&region, self.config.samples)?);
+        }
+        render_tiles(&rd, self.config.tile_size, writer, |image| {
+            self.raytrace(&rd, rd.camera_data, image)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use slog::Logger;
+
+    use crate::{
+        aabb::Aabb,
+        background_color::SolidBackgroundColor,
+        camera::{CameraData, CameraInitData},
+        config::{CameraConfig, Config},
+        data_bus::DataBus,
+        geometry::{HitRecord, Hittable},
+        image::Image,
+        ray::Ray,
+        renderer::{checkpoint::Checkpoint, RenderProgress},
+    };
+
+    use super::*;
+
+    struct Empty(Aabb);
+
+    impl Hittable for Empty {
+        fn hit(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> Option<HitRecord> {
+            None
+        }
+
+        fn bounding_box(&self, _time_a: f64, _time_b: f64) -> &Aabb {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() {
+        let image = Image::new(4, 2);
+        let camera_data = CameraData::merge(CameraConfig::default(), CameraConfig::default());
+        let mut camera = Camera::new(
+            CameraInitData::try_new(&camera_data, &image).unwrap(),
+            &image,
+        );
+        let camera = camera.get_shared_camera();
+        let config = Config {
+            render: RenderConfig {
+                samples: 4,
+                max_depth: 2,
+                scale: 1,
+                tile_size: 32,
+            },
+            ..Config::default()
+        };
+
+        // The first pixel is done and the second halfway.
+        let dir = std::env::temp_dir().join(format!("racer-tracer-test-{}", std::process::id()));
+        let checkpoint = Checkpoint::new(
+            Logger::root(slog::Discard, o!()),
+            &dir,
+            "resume",
+            &image,
+            false,
+            Duration::from_secs(3600),
+        );
+        let first = Region {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        checkpoint
+            .add_tile(
+                &first,
+                &[Color::new(2.0, 2.0, 2.0), Color::new(1.0, 1.0, 1.0)],
+                &[4, 2],
+            )
+            .unwrap();
+
+        let progress = RenderProgress::new();
+        progress.begin(true, 4, image.width * image.height);
+        let bus = DataBus::<ImageBufferEvent>::new("test");
+        CpuRenderer::new(config.render.clone())
+            .render(
+                RenderData {
+                    camera_data: camera.data(),
+                    image: &image,
+                    scene: &Empty(Aabb::new(Vec3::default(), Vec3::default())),
+                    background: &SolidBackgroundColor::new(Color::new(1.0, 1.0, 1.0)),
+                    config: &config,
+                    cancel_event: None,
+                    progress: &progress,
+                    region: None,
+                    checkpoint: Some(&checkpoint),
+                    objects: None,
+                },
+                &bus.get_writer(),
+            )
+            .unwrap();
+
+        // The stored sums are kept and only the missing samples taken.
+        let (sums, counts) = checkpoint.tile(&first).unwrap();
+        assert_eq!(sums, [Color::new(2.0, 2.0, 2.0), Color::new(3.0, 3.0, 3.0)]);
+        assert_eq!(counts, [4, 4]);
+        let info = progress.info();
+        assert_eq!((info.done, info.resumed), (4 * 8, 6));
+        assert_eq!(info.rays, 4 * 8 - 6);
+        assert_eq!(info.rays_per_pixel(), 4.0);
+    }
+}
@@ -1,45 +1,26 @@
-use rayon::prelude::*;
-
 use crate::{
     camera::{Camera, CameraSharedData},
     config::RenderConfig,
     data_bus::DataWriter,
     error::TracerError,
-    image::{Image, SubImage},
+    image::SubImage,
     image_buffer::ImageBufferEvent,
-    renderer::{cpu::CpuRenderer, do_cancel, ray_color, Renderer},
+    renderer::{do_cancel, ray_color, tiles::render_tiles, Renderer},
     util::random_double,
     vec3::{Color, Vec3},
 };
 
 use super::RenderData;
 
-fn get_highest_divdable(value: usize, mut div: usize) -> usize {
-    // Feels like there could possibly be some other nicer trick to this.
-    while (value % div) != 0 {
-        div -= 1;
-    }
-    div
-}
-
 pub struct CpuRendererScaled {
     config: RenderConfig,
-    scale_width: usize,
-    scale_height: usize,
+    scale: usize,
 }
 
 impl CpuRendererScaled {
-    pub fn new(config: RenderConfig, image: &Image) -> Self {
-        let scale_width =
-            get_highest_divdable(image.width / config.num_threads_width, config.scale);
-        let scale_height =
-            get_highest_divdable(image.height / config.num_threads_height, config.scale);
-
-        Self {
-            config,
-            scale_width,
-            scale_height,
-        }
+    pub fn new(config: RenderConfig) -> Self {
+        let scale = config.scale.max(1);
+        Self { config, scale }
     }
 
     pub fn raytrace(
@@ -48,53 +29,73 @@ impl CpuRendererScaled {
         camera_data: &CameraSharedData,
         image: SubImage<ImageBufferEvent>,
     ) -> Result<(), TracerError> {
-        let scaled_width = image.width / self.scale_width;
-        let scaled_height = image.height / self.scale_height;
+        // Blocks at the right and bottom edge of a tile are cut short
+        // when the tile isn't evenly divisible by the scale.
+        let scaled_width = (image.width + self.scale - 1) / self.scale;
+        let scaled_height = (image.height + self.scale - 1) / self.scale;
         let mut buffer = vec![Vec3::default(); image.height * image.width];
+        let mut obj_id = vec![0; image.height * image.width];
 
         for row in 0..scaled_height {
             for column in 0..scaled_width {
-                let u: f64 = ((image.x + column * self.scale_width) as f64 + random_double())
+                let u: f64 = ((image.x + column * self.scale) as f64 + random_double())
                     / (image.screen_width - 1) as f64;
                 let mut color = Color::default();
-                for _ in 0..self.config.samples {
-                    let v: f64 = ((image.y + row * self.scale_height) as f64 + random_double())
+                let mut id = 0;
+                for sample in 0..self.config.samples {
+                    let v: f64 = ((image.y + row * self.scale) as f64 + random_double())
                         / (image.screen_height - 1) as f64;
-                    color.add(
-                        ray_color(
-                            rd.scene,
-                            &Camera::get_ray(camera_data, u, v),
-                            rd.background,
-                            self.config.max_depth,
-                            &camera_data.origin,
-                        )
-                        .rgb,
+                    let data = ray_color(
+                        rd.scene,
+                        &Camera::get_ray(camera_data, u, v),
+                        rd.background,
+                        self.config.max_depth,
+                        &camera_data.origin,
                     );
+                    if sample == 0 {
+                        id = data.obj_id;
+                    }
+                    color.add(data.rgb);
                 }
 
                 // Scale up color
-                color = color.scale_sqrt(self.config.samples);
-                let upscaled_row = row * self.scale_height;
-                let upscaled_col = column * self.scale_width;
-                for scale_h in 0..self.scale_height {
-                    for scale_w in 0..self.scale_width {
-                        buffer[(scale_h + upscaled_row) * image.width + scale_w + upscaled_col] =
-                            color;
+                color = color.average(self.config.samples);
+                let upscaled_row = row * self.scale;
+                let upscaled_col = column * self.scale;
+                for scale_h in upscaled_row..(upscaled_row + self.scale).min(image.height) {
+                    for scale_w in upscaled_col..(upscaled_col + self.scale).min(image.width) {
+                        let index = scale_h * image.width + scale_w;
+                        buffer[index] = color;
+                        obj_id[index] = id;
                     }
                 }
             }
+            let block_height = self.scale.min(image.height - row * self.scale);
+            rd.progress
+                .add(image.width * block_height * self.config.samples);
 
             if do_cancel(rd.cancel_event) {
                 return Ok(());
             }
         }
-        image.writer.write(ImageBufferEvent::BufferUpdate {
-            rgb: buffer,
-            r: image.y,
-            c: image.x,
-            width: image.width,
-            height: image.height,
-        })
+        image
+            .writer
+            .write(ImageBufferEvent::ObjectIdUpdate {
+                obj_id,
+                r: image.y,
+                c: image.x,
+                width: image.width,
+                height: image.height,
+            })
+            .and_then(|_| {
+                image.writer.write(ImageBufferEvent::BufferUpdate {
+                    rgb: buffer,
+                    r: image.y,
+                    c: image.x,
+                    width: image.width,
+                    height: image.height,
+                })
+            })
     }
 }
 
@@ -104,11 +105,8 @@ impl Renderer for CpuRendererScaled {
         rd: RenderData,
         writer: &DataWriter<ImageBufferEvent>,
     ) -> Result<(), crate::error::TracerError> {
-        CpuRenderer::prepare_threads(&rd, &self.config, writer).and_then(|images| {
-            images
-                .into_par_iter()
-                .map(|image| self.raytrace(&rd, rd.camera_data, image))
-                .collect::<Result<(), TracerError>>()
+        render_tiles(&rd, self.config.tile_size, writer, |image| {
+            self.raytrace(&rd, rd.camera_data, image)
         })
     }
 }
@@ -1,5 +1,3 @@
-use rayon::prelude::*;
-
 use crate::{
     camera::{Camera, CameraSharedData},
     config::RenderConfig,
@@ -12,7 +10,7 @@ use crate::{
     vec3::{Color, Vec3},
 };
 
-use super::{cpu::CpuRenderer, RenderData};
+use super::{tiles::render_tiles, RenderData};
 
 // TODO:
 // - Implement SVGF
@@ -264,6 +262,7 @@ impl DenoisedRenderer {
                 depth[index] = data.depth;
                 obj_id[index] = data.obj_id;
             }
+            rd.progress.add(image.width);
 
             if do_cancel(rd.cancel_event) {
                 return Ok(());
@@ -298,11 +297,8 @@ impl Renderer for DenoisedRenderer {
         let denoise_writer = denoiser.get_writer();
 
         // Seed the denoiser with initial data.
-        CpuRenderer::prepare_threads(&rd, &self.config, &denoise_writer).and_then(|images| {
-            images
-                .into_par_iter()
-                .map(|image| self.raytrace(&rd, rd.camera_data, image))
-                .collect::<Result<(), TracerError>>()
+        render_tiles(&rd, self.config.tile_size, &denoise_writer, |image| {
+            self.raytrace(&rd, rd.camera_data, image)
         })?;
         denoiser.initialize_buffers()?;
 
@@ -313,15 +309,11 @@ impl Renderer for DenoisedRenderer {
                 return Ok(());
             }
 
-            res = CpuRenderer::prepare_threads(&rd, &self.config, &denoise_writer)
-                .and_then(|images| {
-                    images
-                        .into_par_iter()
-                        .map(|image| self.raytrace(&rd, rd.camera_data, image))
-                        .collect::<Result<(), TracerError>>()
-                })
-                .and_then(|_| denoiser.update())
-                .and_then(|_| denoiser.denoise(index));
+            res = render_tiles(&rd, self.config.tile_size, &denoise_writer, |image| {
+                self.raytrace(&rd, rd.camera_data, image)
+            })
+            .and_then(|_| denoiser.update())
+            .and_then(|_| denoiser.denoise(index));
             index += 1;
         }
 
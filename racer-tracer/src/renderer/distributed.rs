@@ -0,0 +1,423 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+
+use crate::{
+    camera::CameraState,
+    config::{Config, RenderConfig, SceneLoaderConfig},
+    data_bus::DataWriter,
+    error::TracerError,
+    image::Region,
+    image_buffer::ImageBufferEvent,
+    renderer::{
+        cpu::{write_tile, CpuRenderer},
+        do_cancel,
+        tiles::tile_layout,
+        RenderData, Renderer,
+    },
+    scene::PlacedObject,
+    vec3::Color,
+};
+
+const DEFAULT_TIMEOUT: u64 = 300;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Well above the largest tile or scene file. Anything longer is not
+// from a worker or coordinator.
+const MAX_MESSAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+// Every message is sent as JSON prefixed with its length.
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    // Everything a worker needs to render the same image. The scene
+    // file is sent along when there is one and so are the objects
+    // when they have been edited since it was loaded.
+    Setup {
+        config: Box<Config>,
+        scene: Option<String>,
+        camera: CameraState,
+        #[serde(default)]
+        objects: Option<Vec<PlacedObject>>,
+    },
+    Tile {
+        id: usize,
+        tile: Region,
+    },
+    // The worker is ready for this many tiles at once.
+    Ready {
+        threads: usize,
+    },
+    // Rays traced by the worker since its last finished tile.
+    TileDone {
+        id: usize,
+        rgb: Vec<Color>,
+        obj_id: Vec<usize>,
+        #[serde(default)]
+        rays: u64,
+        #[serde(default)]
+        node_visits: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Message) -> Result<(), TracerError> {
+    serde_json::to_vec(message)
+        .map_err(|e| TracerError::Distributed(e.to_string()))
+        .and_then(|data| {
+            writer
+                .write_all(&(data.len() as u32).to_be_bytes())
+                .and_then(|_| writer.write_all(&data))
+                .and_then(|_| writer.flush())
+                .map_err(|e| TracerError::Distributed(e.to_string()))
+        })
+}
+
+pub fn read_message(reader: &mut impl Read) -> Result<Message, TracerError> {
+    let mut length = [0; 4];
+    reader
+        .read_exact(&mut length)
+        .map_err(|e| TracerError::Distributed(e.to_string()))?;
+
+    let length = u64::from(u32::from_be_bytes(length));
+    if length > MAX_MESSAGE_SIZE {
+        return Err(TracerError::Distributed(format!(
+            "Message of {} bytes is larger than {} bytes",
+            length, MAX_MESSAGE_SIZE
+        )));
+    }
+
+    // Grows as the data arrives instead of trusting the length up
+    // front.
+    let mut data = Vec::new();
+    reader
+        .take(length)
+        .read_to_end(&mut data)
+        .map_err(|e| TracerError::Distributed(e.to_string()))
+        .and_then(|read| {
+            (read as u64 == length).then_some(()).ok_or_else(|| {
+                TracerError::Distributed(String::from("Connection closed mid message"))
+            })
+        })?;
+    serde_json::from_slice(&data).map_err(|e| TracerError::Distributed(e.to_string()))
+}
+
+// Splits the final render into tiles and sends them out to worker
+// processes. Tiles a worker had when it failed are handed to the
+// others. Whatever no worker could take is rendered here.
+pub struct DistributedRenderer {
+    config: RenderConfig,
+    workers: Vec<String>,
+    timeout: Duration,
+    log: Logger,
+}
+
+impl DistributedRenderer {
+    pub fn new(
+        config: RenderConfig,
+        workers: Vec<String>,
+        timeout: Option<u64>,
+        log: Logger,
+    ) -> Self {
+        Self {
+            config,
+            workers,
+            timeout: Duration::from_secs(timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            log,
+        }
+    }
+
+    fn connect(&self, address: &str, setup: &Message) -> Result<(TcpStream, usize), TracerError> {
+        let error = |e: std::io::Error| TracerError::Distributed(format!("{}: {}", address, e));
+        let mut stream = address
+            .to_socket_addrs()
+            .map_err(error)?
+            .next()
+            .ok_or_else(|| TracerError::Distributed(format!("{}: No address", address)))
+            .and_then(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(error))?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_nodelay(true))
+            .map_err(error)?;
+
+        write_message(&mut stream, setup)?;
+        match read_message(&mut stream)? {
+            Message::Ready { threads } => Ok((stream, threads.max(1))),
+            Message::Failed { error } => Err(TracerError::Distributed(format!(
+                "{} failed to load the scene: {}",
+                address, error
+            ))),
+            _ => Err(TracerError::Distributed(format!(
+                "{} sent an unexpected message",
+                address
+            ))),
+        }
+    }
+
+    // Keeps the worker busy until there are no tiles left. Returns
+    // how many tiles it rendered.
+    fn serve_worker(
+        &self,
+        address: &str,
+        rd: &RenderData,
+        tiles: &[Region],
+        queue: &TileQueue,
+        writer: &DataWriter<ImageBufferEvent>,
+        in_flight: &mut Vec<(usize, Instant)>,
+    ) -> Result<usize, TracerError> {
+        let setup = Message::Setup {
+            config: Box::new(rd.config.clone()),
+            scene: match &rd.config.loader {
+                SceneLoaderConfig::Yml { path } => {
+                    Some(fs::read_to_string(path).map_err(|e| {
+                        TracerError::Distributed(format!("{}: {}", path.display(), e))
+                    })?)
+                }
+                _ => None,
+            },
+            camera: rd.camera_data.state(),
+            objects: rd.objects.map(|objects| objects.to_vec()),
+        };
+        let (mut stream, threads) = self.connect(address, &setup)?;
+        let mut reader = BufReader::new(
+            stream
+                .try_clone()
+                .map_err(|e| TracerError::Distributed(e.to_string()))?,
+        );
+        info!(
+            self.log,
+            "Connected to {} with {} threads.", address, threads
+        );
+
+        let mut done = 0;
+        loop {
+            if do_cancel(rd.cancel_event) {
+                return Ok(done);
+            }
+
+            while in_flight.len() < threads {
+                match queue.pop()? {
+                    Some(id) => {
+                        write_message(
+                            &mut stream,
+                            &Message::Tile {
+                                id,
+                                tile: tiles[id],
+                            },
+                        )?;
+                        in_flight.push((id, Instant::now()));
+                    }
+                    None => break,
+                }
+            }
+
+            if in_flight.is_empty() {
+                return Ok(done);
+            }
+
+            match read_message(&mut reader)? {
+                Message::TileDone {
+                    id,
+                    rgb,
+                    obj_id,
+                    rays,
+                    node_visits,
+                } => {
+                    let (_, sent) = in_flight
+                        .iter()
+                        .position(|(tile_id, _)| *tile_id == id)
+                        .map(|index| in_flight.swap_remove(index))
+                        .ok_or_else(|| {
+                            TracerError::Distributed(format!(
+                                "{} sent tile {} which it was never given",
+                                address, id
+                            ))
+                        })?;
+                    let tile = tiles[id];
+                    if rgb.len() != tile.area() || obj_id.len() != tile.area() {
+                        return Err(TracerError::Distributed(format!(
+                            "{} sent tile {} with the wrong size",
+                            address, id
+                        )));
+                    }
+
+                    write_tile(writer, &tile, rgb, obj_id)?;
+                    rd.progress.add(tile.area() * rd.config.render.samples);
+                    rd.progress.add_rays(rays, node_visits);
+                    rd.progress.add_tile_time(&tile, sent.elapsed());
+                    done += 1;
+                }
+                Message::Failed { error } => {
+                    return Err(TracerError::Distributed(format!("{}: {}", address, error)))
+                }
+                _ => {
+                    return Err(TracerError::Distributed(format!(
+                        "{} sent an unexpected message",
+                        address
+                    )))
+                }
+            }
+        }
+    }
+}
+
+// Tiles that still need a worker, centre first.
+struct TileQueue {
+    tiles: Mutex<VecDeque<usize>>,
+}
+
+impl TileQueue {
+    fn lock(&self) -> Result<MutexGuard<'_, VecDeque<usize>>, TracerError> {
+        self.tiles
+            .lock()
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("tile-queue: {}", e)))
+    }
+
+    fn pop(&self) -> Result<Option<usize>, TracerError> {
+        self.lock().map(|mut tiles| tiles.pop_front())
+    }
+
+    // Tiles from a failed worker go first in line again.
+    fn requeue(&self, ids: impl Iterator<Item = usize>) -> Result<(), TracerError> {
+        self.lock()
+            .map(|mut tiles| ids.for_each(|id| tiles.push_front(id)))
+    }
+}
+
+impl Renderer for DistributedRenderer {
+    fn render(
+        &self,
+        rd: RenderData,
+        writer: &DataWriter<ImageBufferEvent>,
+    ) -> Result<(), TracerError> {
+        if do_cancel(rd.cancel_event) {
+            return Err(TracerError::CancelEvent);
+        }
+
+        let region = rd.region.unwrap_or(Region {
+            x: 0,
+            y: 0,
+            width: rd.image.width,
+            height: rd.image.height,
+        });
+        let tiles = tile_layout(&region, self.config.tile_size);
+        rd.progress.set_tile_count(tiles.len());
+        let queue = TileQueue {
+            tiles: Mutex::new((0..tiles.len()).collect()),
+        };
+
+        // Waiting on the network would only block rayon threads so
+        // every worker gets a thread of its own.
+        std::thread::scope(|s| {
+            self.workers.iter().for_each(|address| {
+                let (rd, tiles, queue) = (&rd, &tiles, &queue);
+                s.spawn(move || {
+                    let mut in_flight = Vec::new();
+                    match self.serve_worker(address, rd, tiles, queue, writer, &mut in_flight) {
+                        Ok(done) => info!(self.log, "{} rendered {} tiles.", address, done),
+                        Err(e) => {
+                            warn!(
+                                self.log,
+                                "Worker {} failed, its {} tiles go back in the queue: {}",
+                                address,
+                                in_flight.len(),
+                                e
+                            );
+                            if let Err(e) = queue.requeue(in_flight.into_iter().map(|(id, _)| id)) {
+                                warn!(self.log, "Lost tiles from {}: {}", address, e);
+                            }
+                        }
+                    }
+                });
+            })
+        });
+
+        if do_cancel(rd.cancel_event) {
+            return Ok(());
+        }
+
+        let remaining = queue.lock()?.drain(..).collect::<Vec<usize>>();
+        if remaining.is_empty() {
+            return Ok(());
+        }
+
+        warn!(
+            self.log,
+            "No worker left for {} tiles, rendering them here.",
+            remaining.len()
+        );
+        // The workers don't know about checkpoints so the tiles left
+        // aren't resumed either.
+        let rd = RenderData {
+            checkpoint: None,
+            ..rd
+        };
+        let local = CpuRenderer::new(self.config.clone());
+        remaining.into_par_iter().try_for_each(|id| {
+            let tile = tiles[id];
+            let start = Instant::now();
+            match local.trace(&rd, rd.camera_data, &tile)? {
+                Some((rgb, obj_id)) => write_tile(writer, &tile, rgb, obj_id).map(|_| {
+                    rd.progress.add_tile_time(&tile, start.elapsed());
+                }),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_framing() {
+        let mut bytes = Vec::new();
+        write_message(
+            &mut bytes,
+            &Message::TileDone {
+                id: 3,
+                rgb: vec![Color::new(0.5, 0.25, 1.0); 2],
+                obj_id: vec![7, 8],
+                rays: 12,
+                node_visits: 34,
+            },
+        )
+        .unwrap();
+        write_message(&mut bytes, &Message::Ready { threads: 4 }).unwrap();
+
+        let mut reader = bytes.as_slice();
+        match read_message(&mut reader).unwrap() {
+            Message::TileDone {
+                id,
+                rgb,
+                obj_id,
+                rays,
+                node_visits,
+            } => {
+                assert_eq!(id, 3);
+                assert_eq!((rays, node_visits), (12, 34));
+                assert_eq!(rgb[1], Color::new(0.5, 0.25, 1.0));
+                assert_eq!(obj_id, vec![7, 8]);
+            }
+            _ => panic!("Expected a finished tile"),
+        }
+        assert!(matches!(
+            read_message(&mut reader),
+            Ok(Message::Ready { threads: 4 })
+        ));
+        assert!(read_message(&mut reader).is_err());
+
+        let mut reader = &[0xff, 0xff, 0xff, 0xff, b'{'][..];
+        assert!(read_message(&mut reader).is_err());
+    }
+}
@@ -1,7 +1,10 @@
-// Just for comparing images with different tone maps.
+// Just for comparing images with different tone maps. Shows an image
+// from disk, used as the reference of the split view.
 
 use std::path::Path;
 
+use img::DynamicImage;
+
 use crate::{
     data_bus::DataWriter, error::TracerError, image::Image, image_buffer::ImageBufferEvent,
     vec3::Color,
@@ -11,38 +14,60 @@ use super::{RenderData, Renderer};
 
 pub struct ImageDisplayer {
     buffer: Vec<Color>,
+    // EXR images are linear and need to be post processed. Others are
+    // shown as they are.
+    linear: bool,
 }
 
 impl ImageDisplayer {
-    #[allow(dead_code)]
     pub fn try_new(path: &Path, image: &Image) -> Result<Self, TracerError> {
         image::open(path)
             .map_err(|e| {
                 TracerError::FailedToOpenImage(path.to_string_lossy().into_owned(), e.to_string())
             })
-            .map(|v| v.into_rgba8())
-            .map(|image_buffer| {
-                let mut rgb_buffer = Vec::with_capacity(image.width * image.height);
-                for row in 0..image.height {
-                    for col in 0..image.width {
-                        if row >= image_buffer.height() as usize
-                            || col >= image_buffer.width() as usize
-                        {
-                            rgb_buffer[row * image.width + col] = Color::default();
-                        } else {
-                            let pixel = image_buffer.get_pixel(col as u32, row as u32);
-                            let color_scale = 1.0 / 255.0;
-                            rgb_buffer[row * image.width + col] = Color::new(
-                                f64::from(pixel.0[0]) * color_scale,
-                                f64::from(pixel.0[1]) * color_scale,
-                                f64::from(pixel.0[2]) * color_scale,
-                            );
-                        };
-                    }
+            .and_then(|v| {
+                // Cropped or padded it would be compared with the
+                // wrong pixels.
+                if v.width() as usize != image.width || v.height() as usize != image.height {
+                    return Err(TracerError::FailedToOpenImage(
+                        path.to_string_lossy().into_owned(),
+                        format!(
+                            "The image is {}x{} but the render is {}x{}",
+                            v.width(),
+                            v.height(),
+                            image.width,
+                            image.height
+                        ),
+                    ));
                 }
-                Self { buffer: rgb_buffer }
+                let linear = matches!(
+                    v,
+                    DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+                );
+                Ok(Self {
+                    buffer: v
+                        .into_rgb32f()
+                        .pixels()
+                        .map(|pixel| {
+                            Color::new(
+                                f64::from(pixel.0[0]),
+                                f64::from(pixel.0[1]),
+                                f64::from(pixel.0[2]),
+                            )
+                        })
+                        .collect(),
+                    linear,
+                })
             })
     }
+
+    pub fn buffer(&self) -> &[Color] {
+        &self.buffer
+    }
+
+    pub fn linear(&self) -> bool {
+        self.linear
+    }
 }
 
 impl Renderer for ImageDisplayer {
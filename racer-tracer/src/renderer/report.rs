@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{
+    camera::CameraState,
+    config::{Config, PostProcessConfig, RendererConfig, SceneLoaderConfig, ToneMapConfig},
+    image::Region,
+    renderer::ProgressInfo,
+    scene::PlacedObject,
+};
+
+// What a finished final render was of.
+#[derive(Clone)]
+pub struct FinishedRender {
+    pub camera: CameraState,
+    pub region: Option<Region>,
+    pub progress: ProgressInfo,
+    // Of the scene file when it was loaded.
+    pub scene_sha256: Option<String>,
+    // Where the objects were when they had been edited since the scene
+    // was loaded.
+    pub objects: Option<Vec<PlacedObject>>,
+}
+
+// Samples are taken with an unseeded random generator.
+const SAMPLING_NOTE: &str =
+    "Sampling is not seeded, rendering again gives the same image up to its noise.";
+
+#[derive(Serialize)]
+pub struct SceneReport {
+    path: Option<PathBuf>,
+    sha256: Option<String>,
+    // Index in the loaded scene and position of every object left
+    // when objects were moved or removed. The scene as loaded
+    // otherwise.
+    objects: Option<Vec<PlacedObject>>,
+}
+
+#[derive(Serialize)]
+pub struct RenderStats {
+    seconds: f64,
+    samples: u64,
+    pixels: u64,
+    tiles: u64,
+    rays: u64,
+    rays_per_pixel: f64,
+    node_visits_per_ray: f64,
+    rays_per_second: f64,
+}
+
+impl From<&ProgressInfo> for RenderStats {
+    fn from(info: &ProgressInfo) -> Self {
+        let per = |count: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        Self {
+            seconds: info.elapsed.as_secs_f64(),
+            samples: info.samples,
+            pixels: info.pixels,
+            tiles: info.tiles_done,
+            rays: info.rays,
+            rays_per_pixel: info.rays_per_pixel(),
+            node_visits_per_ray: per(info.node_visits, info.rays),
+            rays_per_second: info.rays_per_second(),
+        }
+    }
+}
+
+// Everything needed to render a saved image again. Written next to
+// the image and embedded in it.
+#[derive(Serialize)]
+pub struct RenderReport {
+    version: &'static str,
+    scene: SceneReport,
+    renderer: RendererConfig,
+    post_process: Vec<PostProcessConfig>,
+    camera: CameraState,
+    region: Option<Region>,
+    stats: RenderStats,
+    config: Config,
+    note: &'static str,
+}
+
+impl RenderReport {
+    pub fn new(
+        config: &Config,
+        post_process: &[PostProcessConfig],
+        render: &FinishedRender,
+    ) -> Self {
+        let path = match &config.loader {
+            SceneLoaderConfig::Yml { path } => Some(path.clone()),
+            _ => None,
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            scene: SceneReport {
+                path,
+                sha256: render.scene_sha256.clone(),
+                objects: render.objects.clone(),
+            },
+            renderer: config.renderer.clone(),
+            post_process: post_process.to_vec(),
+            camera: render.camera,
+            region: render.region,
+            stats: RenderStats::from(&render.progress),
+            config: config.clone(),
+            note: SAMPLING_NOTE,
+        }
+    }
+
+    // The last tone map of the post process stages.
+    pub fn tone_map(&self) -> Option<&ToneMapConfig> {
+        self.post_process
+            .iter()
+            .rev()
+            .find_map(|stage| match stage {
+                PostProcessConfig::ToneMap(tone_map) => Some(tone_map),
+                _ => None,
+            })
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.stats.samples
+    }
+
+    pub fn to_json(&self) -> String {
+        // Only fails for maps with non string keys which there are none
+        // of.
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    data_bus::DataWriter,
+    error::TracerError,
+    image::{Region, SubImage},
+    renderer::{do_cancel, RenderData},
+};
+
+// Splits the region into tiles of at most tile_size pixels. The tiles
+// are ordered in a spiral from the centre and out since that is
+// usually where the interesting parts of the image are.
+pub fn tile_layout(region: &Region, tile_size: usize) -> Vec<Region> {
+    let tile_size = tile_size.max(1);
+    let center_x = region.x as f64 + region.width as f64 / 2.0;
+    let center_y = region.y as f64 + region.height as f64 / 2.0;
+
+    let mut tiles = (0..(region.height + tile_size - 1) / tile_size)
+        .flat_map(|row| {
+            (0..(region.width + tile_size - 1) / tile_size).map(move |column| {
+                let x = region.x + column * tile_size;
+                let y = region.y + row * tile_size;
+                Region {
+                    x,
+                    y,
+                    width: tile_size.min(region.x + region.width - x),
+                    height: tile_size.min(region.y + region.height - y),
+                }
+            })
+        })
+        .collect::<Vec<Region>>();
+
+    // Rings of tiles around the centre, each ring walked by angle.
+    let spiral_key = |tile: &Region| {
+        let dx = (tile.x as f64 + tile.width as f64 / 2.0 - center_x) / tile_size as f64;
+        let dy = (tile.y as f64 + tile.height as f64 / 2.0 - center_y) / tile_size as f64;
+        (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+    };
+    tiles.sort_by(|a, b| {
+        let (ring_a, angle_a) = spiral_key(a);
+        let (ring_b, angle_b) = spiral_key(b);
+        ring_a.total_cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+    });
+    tiles
+}
+
+// Renders all tiles of the image, or the region if there is one. Each
+// rayon worker keeps pulling the next tile in line until there are
+// none left. That way no worker sits idle while another one is stuck
+// with a slow tile.
+pub fn render_tiles<T, F>(
+    rd: &RenderData,
+    tile_size: usize,
+    writer: &DataWriter<T>,
+    render_tile: F,
+) -> Result<(), TracerError>
+where
+    T: Clone + Send + Sync,
+    F: Fn(SubImage<T>) -> Result<(), TracerError> + Send + Sync,
+{
+    if do_cancel(rd.cancel_event) {
+        return Err(TracerError::CancelEvent);
+    }
+
+    let region = rd.region.unwrap_or(Region {
+        x: 0,
+        y: 0,
+        width: rd.image.width,
+        height: rd.image.height,
+    });
+    let tiles = tile_layout(&region, tile_size);
+    rd.progress.set_tile_count(tiles.len());
+    let next = AtomicUsize::new(0);
+
+    (0..rayon::current_num_threads().min(tiles.len()))
+        .into_par_iter()
+        .try_for_each(|_| {
+            while let Some(tile) = tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+                if do_cancel(rd.cancel_event) {
+                    return Ok(());
+                }
+
+                let start = Instant::now();
+                render_tile(SubImage {
+                    x: tile.x,
+                    y: tile.y,
+                    screen_width: rd.image.width,
+                    screen_height: rd.image.height,
+                    width: tile.width,
+                    height: tile.height,
+                    writer: writer.clone(),
+                })?;
+                rd.progress.add_tile_time(tile, start.elapsed());
+            }
+            Ok(())
+        })
+}
+
+// How many of the slowest tiles are listed in the report.
+const SLOWEST_TILES: usize = 5;
+
+// How long each tile took to render in milliseconds, laid out like
+// the tiles in the image, after a summary. Helps finding out if the
+// tile size fits the scene.
+pub fn tile_report(times: &[(Region, Duration)]) -> Option<String> {
+    let total: Duration = times.iter().map(|(_, duration)| *duration).sum();
+    let min = times.iter().map(|(_, duration)| *duration).min()?;
+    let max = times.iter().map(|(_, duration)| *duration).max()?;
+
+    let mut slowest = times.to_vec();
+    slowest.sort_by_key(|(_, duration)| Reverse(*duration));
+    let slowest = slowest
+        .iter()
+        .take(SLOWEST_TILES)
+        .map(|(tile, duration)| {
+            format!(
+                "{}x{} at ({}, {}) {:.1} ms",
+                tile.width,
+                tile.height,
+                tile.x,
+                tile.y,
+                duration.as_secs_f64() * 1000.0
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    // Tiles of a row share the same y.
+    let mut rows: BTreeMap<usize, BTreeMap<usize, Duration>> = BTreeMap::new();
+    for (tile, duration) in times {
+        rows.entry(tile.y).or_default().insert(tile.x, *duration);
+    }
+    let grid = rows
+        .values()
+        .map(|row| {
+            row.values()
+                .map(|duration| format!("{:7.1}", duration.as_secs_f64() * 1000.0))
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Some(format!(
+        "{} tiles, mean {:.1} ms, min {:.1} ms, max {:.1} ms. Slowest: {}\n{}",
+        times.len(),
+        total.as_secs_f64() * 1000.0 / times.len() as f64,
+        min.as_secs_f64() * 1000.0,
+        max.as_secs_f64() * 1000.0,
+        slowest,
+        grid
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_region() {
+        let region = Region {
+            x: 10,
+            y: 20,
+            width: 100,
+            height: 70,
+        };
+        let tiles = tile_layout(&region, 32);
+        assert_eq!(tiles.len(), 4 * 3);
+        assert_eq!(tiles.iter().map(|t| t.area()).sum::<usize>(), region.area());
+        assert!(tiles.iter().all(|t| t.x >= region.x
+            && t.y >= region.y
+            && t.x + t.width <= region.x + region.width
+            && t.y + t.height <= region.y + region.height));
+    }
+
+    #[test]
+    fn starts_in_the_centre() {
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 90,
+            height: 90,
+        };
+        let tiles = tile_layout(&region, 30);
+        assert_eq!(
+            tiles[0],
+            Region {
+                x: 30,
+                y: 30,
+                width: 30,
+                height: 30
+            }
+        );
+
+        // The corners are in the outer ring.
+        assert!(tiles[1..]
+            .iter()
+            .position(|t| t.x == 0 && t.y == 0)
+            .is_some());
+    }
+
+    #[test]
+    fn report_lists_every_tile() {
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 60,
+            height: 40,
+        };
+        let times = tile_layout(&region, 20)
+            .into_iter()
+            .map(|tile| {
+                let ms = (tile.y / 20 * 3 + tile.x / 20 + 1) as u64;
+                (tile, Duration::from_millis(ms))
+            })
+            .collect::<Vec<_>>();
+        let report = tile_report(&times).unwrap();
+        let lines = report.lines().collect::<Vec<&str>>();
+        assert!(lines[0].starts_with("6 tiles, mean 3.5 ms, min 1.0 ms, max 6.0 ms."));
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            ["1.0", "2.0", "3.0"]
+        );
+        assert_eq!(
+            lines[2].split_whitespace().collect::<Vec<_>>(),
+            ["4.0", "5.0", "6.0"]
+        );
+        assert!(tile_report(&[]).is_none());
+    }
+}
@@ -4,23 +4,25 @@ pub mod sandbox;
 pub mod yml;
 
 use dyn_clone::DynClone;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::{
     aabb::Aabb,
     background_color::BackgroundColor,
     camera::{Camera, SharedCamera},
-    config::CameraConfig,
+    config::{CameraConfig, Config, PostProcessConfig, SceneLoaderConfig, ToneMapConfig},
     data_bus::{DataBus, DataReader, DataWriter},
     error::TracerError,
     geometry::{HitRecord, Hittable},
     image::Image,
     material::Material,
     ray::Ray,
-    tone_map::ToneMap,
     vec3::Vec3,
 };
 
+use self::{none::NoneLoader, random::Random, sandbox::Sandbox, yml::YmlLoader};
+
 pub trait HittableSceneObject: Send + Sync + DynClone {
     fn obj_hit(
         &self,
@@ -71,6 +73,12 @@ impl SceneObject {
         self.obj_id
     }
 
+    // Ids are only unique within a process.
+    fn with_id(mut self, obj_id: usize) -> Self {
+        self.obj_id = obj_id;
+        self
+    }
+
     pub fn material(&self) -> Arc<dyn Material> {
         Arc::clone(&self.material)
     }
@@ -110,13 +118,60 @@ pub struct SceneLoadData {
     pub objects: Vec<SceneObject>,
     pub background: Box<dyn BackgroundColor>,
     pub camera: Option<CameraConfig>,
-    pub tone_map: Option<Box<dyn ToneMap>>,
+    pub tone_map: Option<ToneMapConfig>,
+    pub post_process: Option<Vec<PostProcessConfig>>,
+    // Of the scene file as it was loaded.
+    pub sha256: Option<String>,
 }
 
 pub trait SceneLoader: Send + Sync {
     fn load(&self) -> Result<SceneLoadData, TracerError>;
 }
 
+impl From<&Config> for Box<dyn SceneLoader> {
+    fn from(config: &Config) -> Self {
+        match &config.loader {
+            SceneLoaderConfig::Yml { path } => Box::new(YmlLoader::new(path.clone())),
+            SceneLoaderConfig::Random => {
+                Box::new(Random::new(config.scene_seed.unwrap_or_default()))
+            }
+            SceneLoaderConfig::None => Box::new(NoneLoader::new()),
+            SceneLoaderConfig::Sandbox => Box::new(Sandbox::new()),
+        }
+    }
+}
+
+// Where an object of the loaded scene is now. Lets a process that
+// loaded the same scene get the objects as they are after being
+// moved, removed or put back.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlacedObject {
+    // Position of the object in what the loader returned.
+    pub index: usize,
+    pub id: usize,
+    pub pos: Vec3,
+}
+
+// The loaded objects as they are placed.
+pub fn place_objects(
+    loaded: &[SceneObject],
+    placed: &[PlacedObject],
+) -> Result<Vec<SceneObject>, TracerError> {
+    placed
+        .iter()
+        .map(|p| {
+            loaded
+                .get(p.index)
+                .ok_or(TracerError::NoObjectWithId(p.index))
+                .map(|object| {
+                    let mut object = object.clone().with_id(p.id);
+                    object.set_pos(p.pos);
+                    object
+                })
+        })
+        .collect()
+}
+
 // Ensures objects are synced between update and render.
 #[derive(Eq, PartialEq, Hash, Clone, Copy)]
 pub struct ObjectCookie {
@@ -125,13 +180,23 @@ pub struct ObjectCookie {
 
 #[derive(Clone)]
 pub enum SceneObjectEvent {
-    Pos { id: ObjectCookie, pos: Vec3 },
-    Remove { id: ObjectCookie },
-    // TODO: Create events for creation of objects
+    Pos {
+        id: ObjectCookie,
+        pos: Vec3,
+    },
+    Remove {
+        id: ObjectCookie,
+    },
+    Insert {
+        id: ObjectCookie,
+        object: SceneObject,
+    },
 }
 
 pub struct Scene {
     objects: Vec<SceneObject>,
+    // Ids of the objects in the order they were loaded.
+    loaded: Vec<usize>,
     bus: DataBus<SceneObjectEvent>,
     writer: DataWriter<SceneObjectEvent>,
     camera: SharedCamera,
@@ -139,12 +204,11 @@ pub struct Scene {
     image: Image,
 }
 
-// Note: Does not support inserting objects after creation.
-// Will fix that at a later point.
 impl Scene {
     pub fn new(camera: SharedCamera, image: Image, objects: Vec<SceneObject>) -> Self {
         let bus = DataBus::new("scene-object-handler");
         Scene {
+            loaded: objects.iter().map(|object| object.id()).collect(),
             objects,
             writer: bus.get_writer(),
             bus,
@@ -154,20 +218,69 @@ impl Scene {
         }
     }
 
-    pub fn remove_object(&mut self, cookie: &ObjectCookie) -> Result<(), TracerError> {
-        match self.selected_object.as_ref() {
-            Some(id) if id.id == cookie.id => {
-                self.selected_object = None;
-            }
-            _ => {}
+    pub fn objects(&self) -> &[SceneObject] {
+        &self.objects
+    }
+
+    pub fn placed_objects(&self) -> Vec<PlacedObject> {
+        self.objects
+            .iter()
+            .filter_map(|object| {
+                self.loaded
+                    .iter()
+                    .position(|id| *id == object.id())
+                    .map(|index| PlacedObject {
+                        index,
+                        id: object.id(),
+                        pos: object.pos(),
+                    })
+            })
+            .collect()
+    }
+
+    pub fn remove_object(&mut self, cookie: &ObjectCookie) -> Result<SceneObject, TracerError> {
+        if self.objects.get(cookie.id).is_none() {
+            return Err(TracerError::NoObjectWithId(cookie.id));
         }
 
-        if self.objects.get(cookie.id).is_some() {
-            self.objects.remove(cookie.id);
-            self.writer.write(SceneObjectEvent::Remove { id: *cookie })
-        } else {
-            Err(TracerError::NoObjectWithId(cookie.id))
+        // Objects after the removed one move down a step.
+        self.selected_object = match self.selected_object {
+            Some(selected) if selected.id == cookie.id => None,
+            Some(selected) if selected.id > cookie.id => Some(ObjectCookie {
+                id: selected.id - 1,
+            }),
+            selected => selected,
+        };
+
+        let object = self.objects.remove(cookie.id);
+        self.writer
+            .write(SceneObjectEvent::Remove { id: *cookie })
+            .map(|_| object)
+    }
+
+    // Puts an object at the place of the cookie. Objects from there
+    // on move up a step.
+    pub fn insert_object(
+        &mut self,
+        cookie: &ObjectCookie,
+        object: SceneObject,
+    ) -> Result<(), TracerError> {
+        if cookie.id > self.objects.len() {
+            return Err(TracerError::NoObjectWithId(cookie.id));
         }
+
+        self.selected_object = match self.selected_object {
+            Some(selected) if selected.id >= cookie.id => Some(ObjectCookie {
+                id: selected.id + 1,
+            }),
+            selected => selected,
+        };
+
+        self.objects.insert(cookie.id, object.clone());
+        self.writer.write(SceneObjectEvent::Insert {
+            id: *cookie,
+            object,
+        })
     }
 
     pub fn get_shared_objects(&mut self) -> (Vec<SceneObject>, DataReader<SceneObjectEvent>) {
@@ -178,23 +291,56 @@ impl Scene {
         self.selected_object
     }
 
-    pub fn select_object(&mut self, screen_x: f64, screen_y: f64) -> Option<ObjectCookie> {
+    pub fn selected_scene_object(&self) -> Option<&SceneObject> {
+        self.selected_object
+            .and_then(|cookie| self.objects.get(cookie.id))
+    }
+
+    // Finds the closest object under the screen coordinate.
+    fn screen_hit(&self, screen_x: f64, screen_y: f64) -> Option<(ObjectCookie, HitRecord)> {
         let (u, v) = self.image.screen_to_uv(screen_x, screen_y);
-        let ray = Camera::get_ray(self.camera.data(), u, v);
+        let ray = Camera::get_pinhole_ray(self.camera.data(), u, v);
 
         let t_min = 0.001;
-        let t_max = std::f64::INFINITY;
-        self.selected_object = None;
+        let t_max = f64::INFINITY;
         let mut closes_so_far = t_max;
+        let mut closest = None;
 
         for (k, obj) in self.objects.iter().enumerate() {
             if let Some(hit_rec) = obj.hit(&ray, t_min, closes_so_far) {
                 closes_so_far = hit_rec.t;
-                self.selected_object = Some(ObjectCookie { id: k });
+                closest = Some((ObjectCookie { id: k }, hit_rec));
             }
         }
 
+        closest
+    }
+
+    pub fn select_object(&mut self, screen_x: f64, screen_y: f64) -> Option<ObjectCookie> {
+        self.selected_object = self
+            .screen_hit(screen_x, screen_y)
+            .map(|(cookie, _)| cookie);
+        self.selected_object
+    }
+
+    // Point on the closest object under the screen coordinate.
+    pub fn point_at(&self, screen_x: f64, screen_y: f64) -> Option<Vec3> {
+        self.screen_hit(screen_x, screen_y)
+            .map(|(_, hit_rec)| hit_rec.point)
+    }
+
+    // Focus distance needed to get whatever is under the screen
+    // coordinate in focus.
+    pub fn focus_distance_at(&self, screen_x: f64, screen_y: f64) -> Option<f64> {
+        self.screen_hit(screen_x, screen_y)
+            .map(|(_, hit_rec)| self.camera.data().view_distance(&hit_rec.point))
+    }
+
+    // Focus distance needed to get the selected object in focus.
+    pub fn selected_focus_distance(&self) -> Option<f64> {
         self.selected_object
+            .and_then(|cookie| self.get_pos(&cookie).ok())
+            .map(|pos| self.camera.data().view_distance(&pos))
     }
 
     pub fn update(&mut self) -> Result<(), TracerError> {
@@ -17,6 +17,8 @@ impl SceneLoader for NoneLoader {
             background: Box::<Sky>::default(),
             camera: None,
             tone_map: None,
+            post_process: None,
+            sha256: None,
         })
     }
 }
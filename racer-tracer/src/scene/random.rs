@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
 use crate::{
     background_color::Sky,
     config::CameraConfig,
@@ -8,22 +10,34 @@ use crate::{
     material::{dialectric::Dialectric, lambertian::Lambertian, metal::Metal},
     scene::SceneLoader,
     texture::{checkered::Checkered, solid_color::SolidColor},
-    util::{random_double, random_double_range},
     vec3::{Color, Vec3},
 };
 
 use super::{SceneLoadData, SceneObject};
 
-pub struct Random {}
+fn random_color(rng: &mut StdRng, min: f64, max: f64) -> Color {
+    Color::new(
+        rng.gen_range(min..max),
+        rng.gen_range(min..max),
+        rng.gen_range(min..max),
+    )
+}
+
+// Same seed, same scene. Workers generate the scene from the seed
+// of the coordinator.
+pub struct Random {
+    seed: u64,
+}
 
 impl Random {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
     }
 }
 
 impl SceneLoader for Random {
     fn load(&self) -> Result<SceneLoadData, TracerError> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
         let mut geometry: Vec<SceneObject> = Vec::new();
         let checkered = Arc::new(Checkered::new(
             Arc::new(SolidColor::new_from_rgb(0.2, 0.3, 0.1)),
@@ -38,25 +52,26 @@ impl SceneLoader for Random {
 
         for a in -11..11 {
             for b in -11..11 {
-                let choose_mat = random_double();
+                let choose_mat = rng.gen::<f64>();
                 let center = Vec3::new(
-                    a as f64 + 0.9 * random_double(),
+                    a as f64 + 0.9 * rng.gen::<f64>(),
                     0.2,
-                    b as f64 + 0.9 * random_double(),
+                    b as f64 + 0.9 * rng.gen::<f64>(),
                 );
 
                 if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                     if choose_mat < 0.8 {
                         // diffuse
-                        let albedo = Color::random() * Color::random();
+                        let albedo =
+                            random_color(&mut rng, 0.0, 1.0) * random_color(&mut rng, 0.0, 1.0);
                         let mat = Arc::new(Lambertian::new_with_color(albedo));
-                        let center2 = center + Vec3::new(0.0, random_double_range(0.0, 0.5), 0.0);
+                        let center2 = center + Vec3::new(0.0, rng.gen_range(0.0..0.5), 0.0);
 
                         geometry.push(create_movable_sphere(mat, center, center2, 0.2, 0.0, 1.0));
                     } else if choose_mat > 0.95 {
                         // metal
-                        let albedo = Color::random_range(0.5, 1.0);
-                        let fuzz = random_double_range(0.0, 0.5);
+                        let albedo = random_color(&mut rng, 0.5, 1.0);
+                        let fuzz = rng.gen_range(0.0..0.5);
                         let mat = Arc::new(Metal::new_with_color(albedo, fuzz));
                         geometry.push(create_sphere(mat, center, 0.2));
                     } else {
@@ -89,8 +104,17 @@ impl SceneLoader for Random {
                 look_at: Some(Vec3::new(0.0, 0.0, 0.0)),
                 speed: Some(0.000002),
                 sensitivity: None,
+                shutter_open: None,
+                shutter_close: None,
+                shutter_curve: None,
+                aperture_blades: None,
+                aperture_rotation: None,
+                aperture_mask: None,
+                optical_vignetting: None,
             }),
             tone_map: None,
+            post_process: None,
+            sha256: None,
         })
     }
 }
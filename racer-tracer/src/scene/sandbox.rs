@@ -74,8 +74,17 @@ impl Sandbox {
                 look_at: Some(Vec3::new(278.0, 278.0, 0.0)),
                 speed: None,
                 sensitivity: None,
+                shutter_open: None,
+                shutter_close: None,
+                shutter_curve: None,
+                aperture_blades: None,
+                aperture_rotation: None,
+                aperture_mask: None,
+                optical_vignetting: None,
             }),
             tone_map: None,
+            post_process: None,
+            sha256: None,
         })
     }
 }
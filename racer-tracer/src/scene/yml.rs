@@ -1,19 +1,23 @@
 use std::{
     collections::HashMap,
+    fs,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
 use crate::{
     background_color::{BackgroundColor, Sky, SolidBackgroundColor},
     config::CameraConfig,
+    config::PostProcessConfig,
     config::ToneMapConfig,
     error::TracerError,
+    geometry::animated::Keyframe,
     geometry_creation::{
-        create_box, create_rotate_y, create_sphere, create_translate, create_xy_rect,
-        create_xz_rect, create_yz_rect,
+        create_animated, create_box, create_rotate_y, create_sphere, create_translate,
+        create_xy_rect, create_xz_rect, create_yz_rect,
     },
     material::{
         dialectric::Dialectric, diffuse_light::DiffuseLight, lambertian::Lambertian, metal::Metal,
@@ -26,23 +30,43 @@ use crate::{
     vec3::{Color, Vec3},
 };
 
-use config::File;
+use config::{File, FileFormat};
 
 use super::{SceneLoadData, SceneObject};
 
 pub struct YmlLoader {
     path: PathBuf,
+    // Contents of the scene file when it was sent from somewhere else.
+    source: Option<String>,
 }
 
 impl YmlLoader {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, source: None }
+    }
+
+    pub fn from_source(path: PathBuf, source: String) -> Self {
+        Self {
+            path,
+            source: Some(source),
+        }
     }
 }
 
 impl SceneLoader for YmlLoader {
     fn load(&self) -> Result<SceneLoadData, TracerError> {
-        SceneData::from_file(PathBuf::from(&self.path)).and_then(|data| data.try_into())
+        let source = match &self.source {
+            Some(source) => source.clone(),
+            None => fs::read_to_string(&self.path).map_err(|e| {
+                TracerError::Configuration(self.path.to_string_lossy().into_owned(), e.to_string())
+            })?,
+        };
+        SceneData::from_source(&self.path, &source)
+            .and_then(|data| data.try_into())
+            .map(|data| SceneLoadData {
+                sha256: Some(format!("{:X}", Sha256::digest(&source))),
+                ..data
+            })
     }
 }
 
@@ -85,6 +109,15 @@ enum MaterialData {
     },
 }
 
+// Keyframes can be added to any geometry. Translations and rotations
+// left out of a keyframe keep the value of the keyframe before it.
+#[derive(Debug, Deserialize)]
+struct KeyframeData {
+    time: f64,
+    translate: Option<Vec3>,
+    rotate: Option<Vec3>,
+}
+
 #[derive(Debug, Deserialize)]
 enum GeometryData {
     Sphere {
@@ -92,6 +125,7 @@ enum GeometryData {
         pos: Vec3,
         radius: f64,
         material: String,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     XyRect {
         x0: f64,
@@ -100,6 +134,7 @@ enum GeometryData {
         y1: f64,
         k: f64,
         material: String,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     XzRect {
         x0: f64,
@@ -108,6 +143,7 @@ enum GeometryData {
         z1: f64,
         k: f64,
         material: String,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     YzRect {
         y0: f64,
@@ -116,23 +152,53 @@ enum GeometryData {
         z1: f64,
         k: f64,
         material: String,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     Box {
         min: Vec3,
         max: Vec3,
         material: String,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     RotateY {
         key: String,
         degrees: f64,
+        keyframes: Option<Vec<KeyframeData>>,
     },
     Translate {
         key: String,
         #[serde(flatten)]
         pos: Vec3,
+        keyframes: Option<Vec<KeyframeData>>,
     },
 }
 
+fn animate(keyframes: Option<Vec<KeyframeData>>, obj: SceneObject) -> SceneObject {
+    match keyframes {
+        Some(mut keyframes) if !keyframes.is_empty() => {
+            keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+            let mut translate = Vec3::default();
+            let mut rotate = Vec3::default();
+            create_animated(
+                keyframes
+                    .into_iter()
+                    .map(|k| {
+                        translate = k.translate.unwrap_or(translate);
+                        rotate = k.rotate.unwrap_or(rotate);
+                        Keyframe {
+                            time: k.time,
+                            translate,
+                            rotate,
+                        }
+                    })
+                    .collect(),
+                obj,
+            )
+        }
+        _ => obj,
+    }
+}
+
 #[derive(Debug, Deserialize)]
 enum Background {
     Sky { top: Vec3, bottom: Vec3 },
@@ -147,25 +213,17 @@ struct SceneData {
     background: Option<Background>,
     camera: Option<CameraConfig>,
     tone_map: Option<ToneMapConfig>,
+    post_process: Option<Vec<PostProcessConfig>>,
 }
 
 impl SceneData {
-    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Self, TracerError> {
+    pub fn from_source(path: &Path, source: &str) -> Result<Self, TracerError> {
         config::Config::builder()
-            .add_source(File::from(file.as_ref()))
+            .add_source(File::from_str(source, FileFormat::Yaml))
             .build()
+            .and_then(|c| c.try_deserialize())
             .map_err(|e| {
-                TracerError::Configuration(
-                    file.as_ref().to_string_lossy().into_owned(),
-                    e.to_string(),
-                )
-            })?
-            .try_deserialize()
-            .map_err(|e| {
-                TracerError::Configuration(
-                    file.as_ref().to_string_lossy().into_owned(),
-                    e.to_string(),
-                )
+                TracerError::Configuration(path.to_string_lossy().into_owned(), e.to_string())
             })
     }
 }
@@ -291,110 +349,115 @@ impl TryInto<SceneLoadData> for SceneData {
 
         self.geometry
             .into_iter()
-            .try_for_each::<_, Result<(), TracerError>>(|(key, geo)| match geo {
-                GeometryData::Sphere {
-                    pos,
-                    radius,
-                    material,
-                } => materials
-                    .get(&material)
-                    .ok_or(TracerError::UnknownMaterial(material))
-                    .and_then(|mat| {
-                        match geometry
-                            .insert(key.clone(), create_sphere(Arc::clone(mat), pos, radius))
-                        {
-                            Some(_) => Err(TracerError::SceneLoad(format!(
-                                "The object \"{}\" was already present in the scene.",
-                                key
-                            ))),
-                            None => Ok(()),
-                        }
-                    }),
-                GeometryData::XyRect {
-                    x0,
-                    x1,
-                    y0,
-                    y1,
-                    k,
-                    material,
-                } => materials
-                    .get(&material)
-                    .ok_or(TracerError::UnknownMaterial(material))
-                    .and_then(|mat| {
-                        match geometry.insert(
-                            key.clone(),
-                            create_xy_rect(Arc::clone(mat), x0, x1, y0, y1, k),
-                        ) {
-                            Some(_) => Err(TracerError::SceneLoad(format!(
-                                "The object \"{}\" was already present in the scene.",
-                                key
-                            ))),
-                            None => Ok(()),
-                        }
-                    }),
-                GeometryData::XzRect {
-                    x0,
-                    x1,
-                    z0,
-                    z1,
-                    k,
-                    material,
-                } => materials
-                    .get(&material)
-                    .ok_or(TracerError::UnknownMaterial(material))
-                    .and_then(|mat| {
-                        match geometry.insert(
+            .try_for_each::<_, Result<(), TracerError>>(|(key, geo)| {
+                let (obj, keyframes) = match geo {
+                    GeometryData::Sphere {
+                        pos,
+                        radius,
+                        material,
+                        keyframes,
+                    } => materials
+                        .get(&material)
+                        .ok_or(TracerError::UnknownMaterial(material))
+                        .map(|mat| (create_sphere(Arc::clone(mat), pos, radius), keyframes))?,
+                    GeometryData::XyRect {
+                        x0,
+                        x1,
+                        y0,
+                        y1,
+                        k,
+                        material,
+                        keyframes,
+                    } => materials
+                        .get(&material)
+                        .ok_or(TracerError::UnknownMaterial(material))
+                        .map(|mat| {
+                            (
+                                create_xy_rect(Arc::clone(mat), x0, x1, y0, y1, k),
+                                keyframes,
+                            )
+                        })?,
+                    GeometryData::XzRect {
+                        x0,
+                        x1,
+                        z0,
+                        z1,
+                        k,
+                        material,
+                        keyframes,
+                    } => materials
+                        .get(&material)
+                        .ok_or(TracerError::UnknownMaterial(material))
+                        .map(|mat| {
+                            (
+                                create_xz_rect(Arc::clone(mat), x0, x1, z0, z1, k),
+                                keyframes,
+                            )
+                        })?,
+                    GeometryData::YzRect {
+                        y0,
+                        y1,
+                        z0,
+                        z1,
+                        k,
+                        material,
+                        keyframes,
+                    } => materials
+                        .get(&material)
+                        .ok_or(TracerError::UnknownMaterial(material))
+                        .map(|mat| {
+                            (
+                                create_yz_rect(Arc::clone(mat), y0, y1, z0, z1, k),
+                                keyframes,
+                            )
+                        })?,
+                    GeometryData::Box {
+                        min,
+                        max,
+                        material,
+                        keyframes,
+                    } => materials
+                        .get(&material)
+                        .ok_or(TracerError::UnknownMaterial(material))
+                        .map(|mat| (create_box(Arc::clone(mat), min, max), keyframes))?,
+                    GeometryData::RotateY {
+                        key,
+                        degrees,
+                        keyframes,
+                    } => {
+                        rotations_y.insert(
                             key.clone(),
-                            create_xz_rect(Arc::clone(mat), x0, x1, z0, z1, k),
-                        ) {
-                            Some(_) => Err(TracerError::SceneLoad(format!(
-                                "The object \"{}\" was already present in the scene.",
-                                key
-                            ))),
-                            None => Ok(()),
-                        }
-                    }),
-                GeometryData::YzRect {
-                    y0,
-                    y1,
-                    z0,
-                    z1,
-                    k,
-                    material,
-                } => materials
-                    .get(&material)
-                    .ok_or(TracerError::UnknownMaterial(material))
-                    .and_then(|mat| {
-                        match geometry.insert(
+                            GeometryData::RotateY {
+                                key,
+                                degrees,
+                                keyframes,
+                            },
+                        );
+                        return Ok(());
+                    }
+                    GeometryData::Translate {
+                        key,
+                        pos,
+                        keyframes,
+                    } => {
+                        translations.insert(
                             key.clone(),
-                            create_yz_rect(Arc::clone(mat), y0, y1, z0, z1, k),
-                        ) {
-                            Some(_) => Err(TracerError::SceneLoad(format!(
-                                "The object \"{}\" was already present in the scene.",
-                                key
-                            ))),
-                            None => Ok(()),
-                        }
-                    }),
-                GeometryData::Box { min, max, material } => materials
-                    .get(&material)
-                    .ok_or(TracerError::UnknownMaterial(material))
-                    .and_then(|mat| {
-                        match geometry.insert(key.clone(), create_box(Arc::clone(mat), min, max)) {
-                            Some(_) => Err(TracerError::SceneLoad(format!(
-                                "The object \"{}\" was already present in the scene.",
-                                key
-                            ))),
-                            None => Ok(()),
-                        }
-                    }),
-                GeometryData::RotateY { key, degrees } => {
-                    rotations_y.insert(key.clone(), GeometryData::RotateY { key, degrees });
-                    Ok(())
-                }
-                GeometryData::Translate { key, pos } => {
-                    translations.insert(key.clone(), GeometryData::Translate { key, pos });
-                    Ok(())
+                            GeometryData::Translate {
+                                key,
+                                pos,
+                                keyframes,
+                            },
+                        );
+                        return Ok(());
+                    }
+                };
+
+                match geometry.insert(key.clone(), animate(keyframes, obj)) {
+                    Some(_) => Err(TracerError::SceneLoad(format!(
+                        "The object \"{}\" was already present in the scene.",
+                        key
+                    ))),
+                    None => Ok(()),
                 }
             })?;
 
@@ -402,7 +465,11 @@ impl TryInto<SceneLoadData> for SceneData {
         rotations_y
             .into_iter()
             .try_for_each::<_, Result<(), TracerError>>(|(child_key, rot)| match rot {
-                GeometryData::RotateY { key, degrees } => geometry
+                GeometryData::RotateY {
+                    key,
+                    degrees,
+                    keyframes,
+                } => geometry
                     .remove(child_key.as_str())
                     .ok_or_else(|| {
                         TracerError::SceneLoad(format!(
@@ -411,7 +478,7 @@ impl TryInto<SceneLoadData> for SceneData {
                         ))
                     })
                     .map(|obj| {
-                        geometry.insert(key, create_rotate_y(degrees, obj));
+                        geometry.insert(key, animate(keyframes, create_rotate_y(degrees, obj)));
                     }),
                 _ => Err(TracerError::SceneLoad(String::from(
                     "Expected rotations to be rotations",
@@ -422,7 +489,11 @@ impl TryInto<SceneLoadData> for SceneData {
         translations
             .into_iter()
             .try_for_each::<_, Result<(), TracerError>>(|(child_key, trans)| match trans {
-                GeometryData::Translate { key, pos } => geometry
+                GeometryData::Translate {
+                    key,
+                    pos,
+                    keyframes,
+                } => geometry
                     .remove(child_key.as_str())
                     .ok_or_else(|| {
                         TracerError::SceneLoad(format!(
@@ -431,7 +502,7 @@ impl TryInto<SceneLoadData> for SceneData {
                         ))
                     })
                     .map(|obj| {
-                        geometry.insert(key, create_translate(pos, obj));
+                        geometry.insert(key, animate(keyframes, create_translate(pos, obj)));
                     }),
                 _ => Err(TracerError::SceneLoad(String::from(
                     "Expected translations to be translations",
@@ -452,7 +523,9 @@ impl TryInto<SceneLoadData> for SceneData {
                 None => Box::<Sky>::default() as Box<dyn BackgroundColor>,
             },
             camera: self.camera,
-            tone_map: self.tone_map.map(|t| (&t).into()),
+            tone_map: self.tone_map,
+            post_process: self.post_process,
+            sha256: None,
         })
     }
 }
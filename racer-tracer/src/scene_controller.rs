@@ -1,5 +1,8 @@
+pub mod history;
 pub mod interactive;
 
+use std::sync::Arc;
+
 use synchronoise::SignalEvent;
 
 use crate::{
@@ -8,9 +11,12 @@ use crate::{
     data_bus::DataWriter,
     error::TracerError,
     geometry::Hittable,
+    image::Region,
     image_buffer::ImageBufferEvent,
     key_inputs::{KeyEvent, ListenKeyEvents, MousePos},
-    scene::Scene,
+    renderer::{report::FinishedRender, RenderProgress},
+    scene::{ObjectCookie, Scene},
+    vec3::Vec3,
 };
 
 pub trait SceneController: Send + Sync {
@@ -35,8 +41,31 @@ pub trait SceneController: Send + Sync {
         rendered_image_completed: &SignalEvent,
     ) -> Result<(), TracerError>;
 
+    // Object edits from outside the window. They are recorded like the
+    // ones made in the window so they can be undone.
+    fn move_object(
+        &self,
+        scene: &mut Scene,
+        cookie: &ObjectCookie,
+        pos: Vec3,
+    ) -> Result<(), TracerError>;
+    fn remove_object(&self, scene: &mut Scene, cookie: &ObjectCookie) -> Result<(), TracerError>;
+
+    // Starts a final render of what the camera currently sees.
+    fn start_render(&self, camera: &Camera, scene: &Scene) -> Result<(), TracerError>;
+
+    // Part of the image final renders are limited to.
+    fn render_region(&self) -> Option<Region>;
+
+    // Progress of the image currently being rendered.
+    fn render_progress(&self) -> Arc<RenderProgress>;
+
+    // The last final render that completed.
+    fn last_render(&self) -> Option<FinishedRender>;
+
     // Called when the application wants to exit.
     fn stop(&self);
 }
 
+#[allow(unused)]
 pub trait SceneRenderer: Send + Sync {}
@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+use crate::{camera::CameraState, scene::ObjectCookie, scene::SceneObject, vec3::Vec3};
+
+// Edits of the same thing closer together than this become a single
+// undo step. Holding a key or dragging the mouse edits every frame.
+const MERGE_WINDOW: Duration = Duration::from_millis(300);
+
+const MAX_UNDO_STEPS: usize = 100;
+
+#[derive(Clone)]
+pub enum Edit {
+    Move {
+        cookie: ObjectCookie,
+        from: Vec3,
+        to: Vec3,
+    },
+    Remove {
+        cookie: ObjectCookie,
+        object: SceneObject,
+    },
+    Camera {
+        from: CameraState,
+        to: CameraState,
+    },
+}
+
+impl Edit {
+    // Folds the next edit into this one if they change the same
+    // thing.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Move { cookie, to, .. },
+                Edit::Move {
+                    cookie: next_cookie,
+                    to: next_to,
+                    ..
+                },
+            ) if cookie == next_cookie => {
+                *to = *next_to;
+                true
+            }
+            (Edit::Camera { to, .. }, Edit::Camera { to: next_to, .. }) => {
+                *to = *next_to;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    // None when the next edit should not be merged.
+    last_edit: Option<Instant>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: None,
+        }
+    }
+
+    pub fn push(&mut self, edit: Edit) {
+        let now = Instant::now();
+        let merged = matches!(self.last_edit, Some(last) if now.duration_since(last) < MERGE_WINDOW)
+            && self.undo.last_mut().map_or(false, |last| last.merge(&edit));
+        if !merged {
+            self.undo.push(edit);
+            if self.undo.len() > MAX_UNDO_STEPS {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.last_edit = Some(now);
+    }
+
+    // The returned edit is to be reverted.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop()?;
+        self.redo.push(edit.clone());
+        // Don't merge whatever comes next into the step before.
+        self.last_edit = None;
+        Some(edit)
+    }
+
+    // The returned edit is to be applied again.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit.clone());
+        self.last_edit = None;
+        Some(edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_edit(id: usize, from: f64, to: f64) -> Edit {
+        Edit::Move {
+            cookie: ObjectCookie { id },
+            from: Vec3::new(from, 0.0, 0.0),
+            to: Vec3::new(to, 0.0, 0.0),
+        }
+    }
+
+    fn assert_move(edit: Option<Edit>, id: usize, from: f64, to: f64) {
+        match edit {
+            Some(Edit::Move {
+                cookie,
+                from: f,
+                to: t,
+            }) => {
+                assert_eq!(cookie.id, id);
+                assert_eq!(*f.x(), from);
+                assert_eq!(*t.x(), to);
+            }
+            _ => panic!("Expected a move"),
+        }
+    }
+
+    #[test]
+    fn drags_merge() {
+        let mut history = History::new();
+        history.push(move_edit(1, 0.0, 1.0));
+        history.push(move_edit(1, 1.0, 2.0));
+        history.push(move_edit(2, 0.0, 1.0));
+
+        assert_move(history.undo(), 2, 0.0, 1.0);
+        assert_move(history.undo(), 1, 0.0, 2.0);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn redo() {
+        let mut history = History::new();
+        history.push(move_edit(1, 0.0, 1.0));
+        assert_move(history.undo(), 1, 0.0, 1.0);
+        assert_move(history.redo(), 1, 0.0, 1.0);
+        assert!(history.redo().is_none());
+
+        // New edits clear what can be redone.
+        history.undo();
+        history.push(move_edit(2, 0.0, 1.0));
+        assert!(history.redo().is_none());
+    }
+}
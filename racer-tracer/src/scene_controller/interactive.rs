@@ -1,6 +1,8 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
 
-use minifb::{Key, MouseButton};
 use slog::Logger;
 use synchronoise::SignalEvent;
 
@@ -8,17 +10,30 @@ use crate::{
     background_color::BackgroundColor,
     camera::{Camera, CameraData, SharedCamera},
     config::Config,
+    controls::{Action, Binding, Controls},
     data_bus::DataWriter,
     error::TracerError,
     geometry::Hittable,
-    image::Image,
+    image::{Image, Region},
     image_buffer::ImageBufferEvent,
     key_inputs::{KeyEvent, ListenKeyEvents, MousePos},
-    renderer::{RenderData, Renderer},
-    scene::Scene,
+    renderer::{
+        checkpoint::{checkpoint_key, Checkpoint},
+        report::FinishedRender,
+        tiles::tile_report,
+        RenderData, RenderProgress, Renderer,
+    },
+    scene::{ObjectCookie, PlacedObject, Scene},
+    vec3::Vec3,
+};
+
+use super::{
+    history::{Edit, History},
+    SceneController,
 };
 
-use super::SceneController;
+// Change in distance to the orbit pivot per scroll step.
+const DOLLY_SPEED: f64 = 0.1;
 
 pub struct InteractiveScene {
     camera_speed: f64,
@@ -26,19 +41,44 @@ pub struct InteractiveScene {
     object_move_speed: f64,
     render_image_event: SignalEvent,
     stop_event: SignalEvent,
+    track_focus_event: SignalEvent,
+    // The camera orbits around the pivot when set. Otherwise it flies
+    // around freely.
+    orbit_pivot: Mutex<Option<Vec3>>,
+    history: Mutex<History>,
+    // Dragging with the look button selects the region to render
+    // instead of looking around when set.
+    region_mode: SignalEvent,
+    region_start: Mutex<Option<MousePos>>,
+    region: Mutex<Option<Region>>,
+    // Identifies what the final render is of so it can be resumed.
+    checkpoint_key: Mutex<Option<String>>,
+    // Objects as they were when the final render was started.
+    placed_objects: Mutex<Option<Vec<PlacedObject>>>,
+    // Start the final render right away to continue from its
+    // checkpoint.
+    resume_event: SignalEvent,
+    controls: Controls,
     log: Logger,
     config: Config,
     image: Image,
     renderer: Box<dyn Renderer>,
     renderer_preview: Box<dyn Renderer>,
+    progress: Arc<RenderProgress>,
+    last_render: Mutex<Option<FinishedRender>>,
+    // Of the scene file when it was loaded.
+    scene_sha256: Option<String>,
 }
 
 impl InteractiveScene {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         log: Logger,
         config: Config,
+        controls: Controls,
         image: Image,
         camera_data: CameraData,
+        scene_sha256: Option<String>,
         renderer: Box<dyn Renderer>,
         renderer_preview: Box<dyn Renderer>,
     ) -> Self {
@@ -48,13 +88,207 @@ impl InteractiveScene {
             object_move_speed: 0.000001,
             render_image_event: SignalEvent::manual(false),
             stop_event: SignalEvent::manual(false),
+            track_focus_event: SignalEvent::manual(false),
+            orbit_pivot: Mutex::new(None),
+            history: Mutex::new(History::new()),
+            region_mode: SignalEvent::manual(false),
+            region_start: Mutex::new(None),
+            region: Mutex::new(None),
+            checkpoint_key: Mutex::new(None),
+            placed_objects: Mutex::new(None),
+            resume_event: SignalEvent::manual(config.resume),
+            controls,
             log,
             image,
             renderer,
             renderer_preview,
+            progress: Arc::new(RenderProgress::new()),
+            last_render: Mutex::new(None),
+            scene_sha256,
             config,
         }
     }
+
+    fn orbit_pivot(&self) -> Result<Option<Vec3>, TracerError> {
+        self.orbit_pivot
+            .lock()
+            .map(|pivot| *pivot)
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("orbit-pivot: {}", e)))
+    }
+
+    fn set_orbit_pivot(&self, pivot: Option<Vec3>) -> Result<(), TracerError> {
+        self.orbit_pivot
+            .lock()
+            .map(|mut p| *p = pivot)
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("orbit-pivot: {}", e)))
+    }
+
+    fn toggle_orbit(
+        &self,
+        mouse_pos: Option<&MousePos>,
+        camera: &mut Camera,
+        scene: &Scene,
+    ) -> Result<(), TracerError> {
+        match self.orbit_pivot()? {
+            Some(_) => {
+                info!(self.log, "Camera mode: fly");
+                self.set_orbit_pivot(None)
+            }
+            None => {
+                // Orbit the selected object, what's under the mouse
+                // or whatever is in focus. In that order.
+                let pivot = scene
+                    .selected_object()
+                    .and_then(|cookie| scene.get_pos(&cookie).ok())
+                    .or_else(|| mouse_pos.and_then(|mp| scene.point_at(mp.x, mp.y)))
+                    .unwrap_or_else(|| {
+                        camera.pos() - camera.forward() * camera.get_focus_distance()
+                    });
+                info!(self.log, "Camera mode: orbit around {}", pivot);
+                self.set_orbit_pivot(Some(pivot))
+                    .and_then(|_| camera.set_look_at(pivot))
+            }
+        }
+    }
+
+    fn region(&self) -> Result<Option<Region>, TracerError> {
+        self.region
+            .lock()
+            .map(|region| *region)
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("region: {}", e)))
+    }
+
+    fn set_region(&self, region: Option<Region>) -> Result<(), TracerError> {
+        self.region
+            .lock()
+            .map(|mut r| *r = region)
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("region: {}", e)))
+    }
+
+    // Updates the region while it is being dragged out.
+    fn drag_region(&self, x: f64, y: f64, mouse_pos: Option<&MousePos>) -> Result<(), TracerError> {
+        let start = self
+            .region_start
+            .lock()
+            .map(|mut start| {
+                mouse_pos.map(|mp| {
+                    *start.get_or_insert(MousePos {
+                        x: mp.x + x,
+                        y: mp.y + y,
+                    })
+                })
+            })
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("region-start: {}", e)))?;
+
+        match (start, mouse_pos) {
+            (Some(start), Some(end)) => self.set_region(Region::from_corners(
+                &self.image,
+                (start.x, start.y),
+                (end.x, end.y),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish_region(&self, start: &MousePos, end: &MousePos) -> Result<(), TracerError> {
+        self.region_mode.reset();
+        self.region_start
+            .lock()
+            .map(|mut s| *s = None)
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("region-start: {}", e)))?;
+
+        let region = Region::from_corners(&self.image, (start.x, start.y), (end.x, end.y));
+        match region {
+            Some(r) => info!(
+                self.log,
+                "Final renders limited to {}x{} pixels at ({}, {}).", r.width, r.height, r.x, r.y
+            ),
+            None => info!(self.log, "Empty region, rendering the full image."),
+        }
+        self.set_region(region)
+    }
+
+    fn move_selected(&self, move_delta: Vec3, scene: &mut Scene) -> Result<(), TracerError> {
+        match scene.selected_object() {
+            Some(cookie) => match scene.get_pos(&cookie) {
+                Ok(from) => {
+                    let to = from + move_delta;
+                    let _ = scene.set_pos(&cookie, to);
+                    self.record(Edit::Move { cookie, from, to })
+                }
+                Err(_) => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn delete_selected(&self, scene: &mut Scene) -> Result<(), TracerError> {
+        match scene.selected_object() {
+            Some(cookie) => match scene.remove_object(&cookie) {
+                Ok(object) => self.record(Edit::Remove { cookie, object }),
+                Err(_) => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn history(&self) -> Result<MutexGuard<'_, History>, TracerError> {
+        self.history
+            .lock()
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("history: {}", e)))
+    }
+
+    fn record(&self, edit: Edit) -> Result<(), TracerError> {
+        self.history().map(|mut history| history.push(edit))
+    }
+
+    fn undo(&self, camera: &mut Camera, scene: &mut Scene) -> Result<(), TracerError> {
+        let edit = self.history()?.undo();
+        let res = match edit {
+            Some(Edit::Move { cookie, from, .. }) => scene.set_pos(&cookie, from),
+            Some(Edit::Remove { cookie, object }) => scene.insert_object(&cookie, object),
+            Some(Edit::Camera { from, .. }) => camera.set_state(&from),
+            None => Ok(()),
+        };
+
+        // The scene is allowed to be out of sync with the history, it
+        // should not take the application down.
+        if let Err(e) = res {
+            warn!(self.log, "Failed to undo: {}", e);
+        }
+        Ok(())
+    }
+
+    fn redo(&self, camera: &mut Camera, scene: &mut Scene) -> Result<(), TracerError> {
+        let edit = self.history()?.redo();
+        let res = match edit {
+            Some(Edit::Move { cookie, to, .. }) => scene.set_pos(&cookie, to),
+            Some(Edit::Remove { cookie, .. }) => scene.remove_object(&cookie).map(|_| ()),
+            Some(Edit::Camera { to, .. }) => camera.set_state(&to),
+            None => Ok(()),
+        };
+
+        if let Err(e) = res {
+            warn!(self.log, "Failed to redo: {}", e);
+        }
+        Ok(())
+    }
+
+    fn checkpoint_key(&self) -> Result<Option<String>, TracerError> {
+        self.checkpoint_key
+            .lock()
+            .map(|key| key.clone())
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("checkpoint-key: {}", e)))
+    }
+
+    fn move_orbit_pivot(&self, pivot: Vec3, camera: &mut Camera) -> Result<(), TracerError> {
+        if self.orbit_pivot()?.is_some() {
+            self.set_orbit_pivot(Some(pivot))
+                .and_then(|_| camera.set_look_at(pivot))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl SceneController for InteractiveScene {
@@ -66,131 +300,159 @@ impl SceneController for InteractiveScene {
         camera: &mut Camera,
         scene: &mut Scene,
     ) -> Result<(), TracerError> {
+        // Camera changes are recorded as a whole after all input is
+        // handled.
+        let mut camera_before = camera.state();
+        if self.resume_event.status() {
+            self.resume_event.reset();
+            self.start_render(camera, scene)?;
+        }
         keys.into_iter().try_for_each(|event| match event {
-            KeyEvent::Released(key) => match key {
-                Key::Q => {
-                    if let Some(mp) = mouse_pos.as_ref() {
-                        scene.select_object(mp.x, mp.y);
-                    }
+            KeyEvent::Released(_) | KeyEvent::Chord(_, _) => match self.controls.pressed(&event) {
+                Some(Action::Select) => mouse_pos
+                    .as_ref()
+                    .and_then(|mp| scene.select_object(mp.x, mp.y))
+                    .and_then(|cookie| scene.get_pos(&cookie).ok())
+                    .map_or(Ok(()), |pos| self.move_orbit_pivot(pos, camera)),
+                Some(Action::Delete) => self.delete_selected(scene),
+                Some(Action::SelectRegion) => {
+                    self.region_mode.signal();
+                    info!(self.log, "Drag to select the region to render.");
                     Ok(())
                 }
-                Key::E => {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.remove_object(cookie);
+                Some(Action::ClearRegion) => {
+                    self.region_mode.reset();
+                    info!(self.log, "Rendering the full image.");
+                    self.set_region(None)
+                }
+                Some(Action::Undo) => self.undo(camera, scene).map(|_| {
+                    camera_before = camera.state();
+                }),
+                Some(Action::Redo) => self.redo(camera, scene).map(|_| {
+                    camera_before = camera.state();
+                }),
+                Some(Action::Render) => self.start_render(camera, scene),
+                Some(Action::Autofocus) => mouse_pos
+                    .as_ref()
+                    .and_then(|mp| scene.focus_distance_at(mp.x, mp.y))
+                    .filter(|distance| *distance > 0.0)
+                    .map_or(Ok(()), |distance| camera.set_focus_distance(distance)),
+                Some(Action::TrackFocus) => {
+                    if self.track_focus_event.status() {
+                        self.track_focus_event.reset();
+                        info!(self.log, "Stopped tracking focus on the selected object.");
+                    } else {
+                        self.track_focus_event.signal();
+                        info!(self.log, "Tracking focus on the selected object.");
                     }
                     Ok(())
                 }
-                Key::R => {
-                    self.render_image_event.signal();
-                    Ok(())
+                Some(Action::ToggleOrbit) => self.toggle_orbit(mouse_pos.as_ref(), camera, scene),
+                Some(Action::OrbitPivot) => mouse_pos
+                    .as_ref()
+                    .and_then(|mp| scene.point_at(mp.x, mp.y))
+                    .map_or(Ok(()), |pos| self.move_orbit_pivot(pos, camera)),
+                Some(Action::FovUp) => camera.set_fov(camera.get_vfov() + 1.0),
+                Some(Action::FovDown) => camera.set_fov(camera.get_vfov() - 1.0),
+                Some(Action::ApertureUp) => camera.set_aperture(camera.get_aperture() + 0.01),
+                Some(Action::ApertureDown) => camera.set_aperture(camera.get_aperture() - 0.01),
+                Some(Action::FocusUp) => {
+                    camera.set_focus_distance(camera.get_focus_distance() + 1.0)
+                }
+                Some(Action::FocusDown) => {
+                    camera.set_focus_distance(camera.get_focus_distance() - 1.0)
                 }
-                Key::NumPadMinus => camera.set_fov(camera.get_vfov() + 1.0),
-                Key::NumPadPlus => camera.set_fov(camera.get_vfov() - 1.0),
-                Key::NumPad8 => camera.set_aperture(camera.get_aperture() + 0.01),
-                Key::NumPad2 => camera.set_aperture(camera.get_aperture() - 0.01),
-                Key::NumPad4 => camera.set_focus_distance(camera.get_focus_distance() + 1.0),
-                Key::NumPad6 => camera.set_focus_distance(camera.get_focus_distance() - 1.0),
                 _ => Ok(()),
             },
-            KeyEvent::Down(key) => match key {
-                Key::Left => {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.get_pos(cookie).and_then(|mut pos| {
-                            pos.add(camera.right() * -dt * self.object_move_speed);
-                            scene.set_pos(cookie, pos)
-                        });
-                        Ok(())
-                    } else {
-                        Ok(())
-                    }
+            KeyEvent::Down(key) => match self.controls.action(&Binding::Key(key)) {
+                Some(Action::ObjectLeft) => {
+                    self.move_selected(camera.right() * -dt * self.object_move_speed, scene)
                 }
-                Key::Right => {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.get_pos(cookie).and_then(|mut pos| {
-                            pos.add(camera.right() * dt * self.object_move_speed);
-                            scene.set_pos(cookie, pos)
-                        });
-                        Ok(())
-                    } else {
-                        Ok(())
-                    }
+                Some(Action::ObjectRight) => {
+                    self.move_selected(camera.right() * dt * self.object_move_speed, scene)
                 }
-                Key::Up => {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.get_pos(cookie).and_then(|mut pos| {
-                            pos.add(camera.forward() * -dt * self.object_move_speed);
-                            scene.set_pos(cookie, pos)
-                        });
-                        Ok(())
-                    } else {
-                        Ok(())
-                    }
+                Some(Action::ObjectForward) => {
+                    self.move_selected(camera.forward() * -dt * self.object_move_speed, scene)
                 }
-                Key::Down => {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.get_pos(cookie).and_then(|mut pos| {
-                            pos.add(camera.forward() * dt * self.object_move_speed);
-                            scene.set_pos(cookie, pos)
-                        });
-                        Ok(())
-                    } else {
-                        Ok(())
-                    }
+                Some(Action::ObjectBack) => {
+                    self.move_selected(camera.forward() * dt * self.object_move_speed, scene)
                 }
-                Key::W => camera.go_forward(-dt * self.camera_speed),
-                Key::A => camera.go_right(-dt * self.camera_speed),
-                Key::S => camera.go_forward(dt * self.camera_speed),
-                Key::D => camera.go_right(dt * self.camera_speed),
+                Some(Action::MoveForward) => camera.go_forward(-dt * self.camera_speed),
+                Some(Action::MoveLeft) => camera.go_right(-dt * self.camera_speed),
+                Some(Action::MoveBack) => camera.go_forward(dt * self.camera_speed),
+                Some(Action::MoveRight) => camera.go_right(dt * self.camera_speed),
                 _ => Ok(()),
             },
-            KeyEvent::MouseDelta(key, x, y) => {
-                if key == MouseButton::Left {
-                    camera.rotate(x * self.camera_sensitivity, y * self.camera_sensitivity)
-                } else if key == MouseButton::Right {
-                    if let Some(cookie) = scene.selected_object().as_ref() {
-                        let _ = scene.get_pos(cookie).and_then(|mut pos| {
-                            let move_delta = camera.up() * y * dt * self.object_move_speed
-                                + camera.right() * -x * dt * self.object_move_speed;
-                            pos.add(move_delta);
-                            scene.set_pos(cookie, pos)
-                        });
-                        Ok(())
-                    } else {
-                        Ok(())
+            KeyEvent::MouseDelta(button, x, y) => {
+                match self.controls.action(&Binding::Mouse(button)) {
+                    Some(Action::Look) if self.region_mode.status() => {
+                        self.drag_region(x, y, mouse_pos.as_ref())
                     }
-                } else {
-                    Ok(())
+                    Some(Action::Look) => match self.orbit_pivot()? {
+                        Some(pivot) => camera.orbit(
+                            &pivot,
+                            x * self.camera_sensitivity,
+                            y * self.camera_sensitivity,
+                        ),
+                        None => {
+                            camera.rotate(x * self.camera_sensitivity, y * self.camera_sensitivity)
+                        }
+                    },
+                    Some(Action::Pan) => match self.orbit_pivot()? {
+                        Some(pivot) => {
+                            // Pan faster the further away the pivot is.
+                            let speed = (camera.pos() - pivot).length() * self.camera_sensitivity;
+                            let move_delta = camera.right() * x * speed - camera.up() * y * speed;
+                            self.set_orbit_pivot(Some(pivot + move_delta))
+                                .and_then(|_| camera.set_pos(camera.pos() + move_delta))
+                        }
+                        None => Ok(()),
+                    },
+                    Some(Action::MoveObject) => self.move_selected(
+                        camera.up() * y * dt * self.object_move_speed
+                            + camera.right() * -x * dt * self.object_move_speed,
+                        scene,
+                    ),
+                    _ => Ok(()),
                 }
             }
-        })
+            KeyEvent::MouseDrag(button, start, end) => {
+                match self.controls.action(&Binding::Mouse(button)) {
+                    Some(Action::Look) if self.region_mode.status() => {
+                        self.finish_region(&start, &end)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            KeyEvent::Scroll(y) => match self.orbit_pivot()? {
+                Some(pivot) => camera.dolly(&pivot, (1.0 - y * DOLLY_SPEED).clamp(0.5, 2.0)),
+                None => Ok(()),
+            },
+        })?;
+
+        let camera_after = camera.state();
+        if camera_after != camera_before {
+            self.record(Edit::Camera {
+                from: camera_before,
+                to: camera_after,
+            })?;
+        }
+
+        // Keep the selected object in focus as it or the camera moves.
+        match scene.selected_focus_distance() {
+            Some(distance)
+                if self.track_focus_event.status()
+                    && distance > 0.0
+                    && (distance - camera.get_focus_distance()).abs() > 1e-6 =>
+            {
+                camera.set_focus_distance(distance)
+            }
+            _ => Ok(()),
+        }
     }
 
     fn register_key_inputs(&self) -> Vec<ListenKeyEvents> {
-        vec![
-            ListenKeyEvents::Release(vec![
-                Key::Q,
-                Key::R,
-                Key::E,
-                Key::NumPadMinus,
-                Key::NumPadPlus,
-                Key::NumPad8,
-                Key::NumPad2,
-                Key::NumPad4,
-                Key::NumPad6,
-            ]),
-            ListenKeyEvents::Down(vec![
-                Key::Left,
-                Key::Right,
-                Key::Up,
-                Key::Down,
-                Key::W,
-                Key::A,
-                Key::S,
-                Key::D,
-            ]),
-            ListenKeyEvents::MouseMove(MouseButton::Left),
-            ListenKeyEvents::MouseMove(MouseButton::Right),
-        ]
+        self.controls.listen_events()
     }
 
     fn render(
@@ -213,8 +475,13 @@ impl SceneController for InteractiveScene {
                 || {
                     // Render preview
                     self.render_image_event.reset();
+                    self.progress.begin(
+                        false,
+                        self.config.preview.samples,
+                        self.image.width * self.image.height,
+                    );
 
-                    self.renderer_preview.render(
+                    let res = self.renderer_preview.render(
                         RenderData {
                             camera_data: camera.data(),
                             image: &self.image,
@@ -222,13 +489,45 @@ impl SceneController for InteractiveScene {
                             background,
                             config: &self.config,
                             cancel_event: None,
+                            progress: &self.progress,
+                            region: None,
+                            checkpoint: None,
+                            objects: None,
                         },
                         image_buffer_writer,
-                    )
+                    );
+                    self.progress.finish();
+                    res
                 },
                 |_| {
-                    let render_time = Instant::now();
                     self.render_image_event.reset();
+                    let region = self.region()?;
+                    let placed_objects = self
+                        .placed_objects
+                        .lock()
+                        .map(|objects| objects.clone())
+                        .map_err(|e| {
+                            TracerError::FailedToAcquireLock(format!("placed-objects: {}", e))
+                        })?;
+                    let checkpoint = self.checkpoint_key()?.map(|key| {
+                        Checkpoint::new(
+                            self.log.new(o!("scope" => "checkpoint")),
+                            &self
+                                .config
+                                .checkpoint_dir
+                                .clone()
+                                .unwrap_or_else(|| std::env::temp_dir().join("racer-tracer")),
+                            &key,
+                            &self.image,
+                            self.config.resume,
+                            Duration::from_secs(self.config.checkpoint_interval),
+                        )
+                    });
+                    self.progress.begin(
+                        true,
+                        self.config.render.samples,
+                        region.map_or(self.image.width * self.image.height, |r| r.area()),
+                    );
 
                     // When we render the final image we want partial
                     // updates to the screen buffer. We send in our
@@ -246,26 +545,113 @@ impl SceneController for InteractiveScene {
                                 background,
                                 config: &self.config,
                                 cancel_event: Some(&self.render_image_event),
+                                progress: &self.progress,
+                                region,
+                                checkpoint: checkpoint.as_ref(),
+                                objects: placed_objects.as_deref(),
                             },
                             image_buffer_writer,
                         )
-                        .map(|_| {
+                        .and_then(|_| {
+                            self.progress.finish();
                             if !self.render_image_event.status() {
+                                let progress = self.progress.info();
+                                info!(self.log, "{}", progress.summary());
+                                // Has to be in place before the image
+                                // action picks up the image.
+                                self.last_render
+                                    .lock()
+                                    .map(|mut last_render| {
+                                        *last_render = Some(FinishedRender {
+                                            camera: camera.data().state(),
+                                            region,
+                                            progress,
+                                            scene_sha256: self.scene_sha256.clone(),
+                                            objects: placed_objects.clone(),
+                                        })
+                                    })
+                                    .map_err(|e| {
+                                        TracerError::FailedToAcquireLock(format!(
+                                            "last-render: {}",
+                                            e
+                                        ))
+                                    })?;
                                 image_completed.signal();
-                                info!(
-                                    self.log,
-                                    "It took {} seconds to render the image.",
-                                    Instant::now().duration_since(render_time).as_secs()
-                                );
+                                if let Some(report) = tile_report(&self.progress.tile_times()) {
+                                    info!(self.log, "{}", report);
+                                }
+                                // The rest of the image might still need
+                                // the checkpoint after a region is done.
+                                match &checkpoint {
+                                    Some(checkpoint) if region.is_none() => {
+                                        checkpoint.remove();
+                                        Ok(())
+                                    }
+                                    Some(checkpoint) => checkpoint.save(),
+                                    None => Ok(()),
+                                }
                             } else {
                                 self.render_image_event.reset();
                                 info!(self.log, "Image render cancelled.");
+                                checkpoint.as_ref().map_or(Ok(()), |c| c.save())
                             }
                         })
                 },
             )
     }
 
+    fn move_object(
+        &self,
+        scene: &mut Scene,
+        cookie: &ObjectCookie,
+        pos: Vec3,
+    ) -> Result<(), TracerError> {
+        let from = scene.get_pos(cookie)?;
+        scene.set_pos(cookie, pos).and_then(|_| {
+            self.record(Edit::Move {
+                cookie: *cookie,
+                from,
+                to: pos,
+            })
+        })
+    }
+
+    fn remove_object(&self, scene: &mut Scene, cookie: &ObjectCookie) -> Result<(), TracerError> {
+        scene.remove_object(cookie).and_then(|object| {
+            self.record(Edit::Remove {
+                cookie: *cookie,
+                object,
+            })
+        })
+    }
+
+    fn start_render(&self, camera: &Camera, scene: &Scene) -> Result<(), TracerError> {
+        self.checkpoint_key
+            .lock()
+            .map(|mut key| *key = Some(checkpoint_key(&self.config, &camera.state(), scene)))
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("checkpoint-key: {}", e)))?;
+        self.placed_objects
+            .lock()
+            .map(|mut objects| *objects = Some(scene.placed_objects()))
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("placed-objects: {}", e)))
+            .map(|_| self.render_image_event.signal())
+    }
+
+    fn render_region(&self) -> Option<Region> {
+        self.region().ok().flatten()
+    }
+
+    fn render_progress(&self) -> Arc<RenderProgress> {
+        Arc::clone(&self.progress)
+    }
+
+    fn last_render(&self) -> Option<FinishedRender> {
+        self.last_render
+            .lock()
+            .ok()
+            .and_then(|render| render.clone())
+    }
+
     fn stop(&self) {
         // If we are currently rendering anything we try to cancel it
         self.render_image_event.signal();
@@ -29,6 +29,10 @@ impl SharedScene {
                     self.objects.remove(id.id);
                     Ok(())
                 }
+                SceneObjectEvent::Insert { id, object } => {
+                    self.objects.insert(id.id.min(self.objects.len()), object);
+                    Ok(())
+                }
                 SceneObjectEvent::Pos { id, pos } => {
                     if let Some(obj) = self.objects.get_mut(id.id) {
                         obj.set_pos(pos);
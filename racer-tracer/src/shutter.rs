@@ -0,0 +1,163 @@
+use crate::{config::ShutterCurveConfig, error::TracerError};
+
+// Describes how much light the shutter lets through while it is
+// open. The efficiency curve is piecewise linear with its samples
+// evenly spread out between the open and close time. Ray times are
+// drawn with the curve as the probability density so that the
+// motion blur gets the same look as the shutter it models.
+#[derive(Clone)]
+pub struct Shutter {
+    open: f64,
+    close: f64,
+    efficiency: Vec<f64>,
+    // Normalized accumulated area up to each efficiency sample.
+    cdf: Vec<f64>,
+}
+
+impl Shutter {
+    pub fn try_new(open: f64, close: f64, curve: &ShutterCurveConfig) -> Result<Self, TracerError> {
+        let efficiency = match curve {
+            ShutterCurveConfig::Box => vec![1.0, 1.0],
+            ShutterCurveConfig::Triangle => vec![0.0, 1.0, 0.0],
+            ShutterCurveConfig::Custom { samples } => samples.clone(),
+        };
+
+        if close < open {
+            return Err(TracerError::InvalidShutter(format!(
+                "Shutter closes ({}) before it opens ({}).",
+                close, open
+            )));
+        }
+
+        if efficiency.len() < 2 {
+            return Err(TracerError::InvalidShutter(String::from(
+                "Shutter curve needs at least two samples.",
+            )));
+        }
+
+        if efficiency.iter().any(|e| *e < 0.0 || !e.is_finite()) {
+            return Err(TracerError::InvalidShutter(String::from(
+                "Shutter curve samples has to be positive numbers.",
+            )));
+        }
+
+        let mut cdf = Vec::with_capacity(efficiency.len());
+        cdf.push(0.0);
+        efficiency.windows(2).for_each(|pair| {
+            let area = cdf.last().copied().unwrap_or(0.0) + (pair[0] + pair[1]) * 0.5;
+            cdf.push(area);
+        });
+
+        let total = cdf.last().copied().unwrap_or(0.0);
+        if total <= 0.0 {
+            return Err(TracerError::InvalidShutter(String::from(
+                "Shutter curve does not let any light through.",
+            )));
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Ok(Self {
+            open,
+            close,
+            efficiency,
+            cdf,
+        })
+    }
+
+    pub fn open(&self) -> f64 {
+        self.open
+    }
+
+    pub fn close(&self) -> f64 {
+        self.close
+    }
+
+    // Maps a uniform random number in [0, 1) to a time following the
+    // shutter curve.
+    pub fn sample(&self, random: f64) -> f64 {
+        let segments = self.efficiency.len() - 1;
+        let segment = self
+            .cdf
+            .partition_point(|c| *c <= random)
+            .clamp(1, segments)
+            - 1;
+
+        // Area left to cover inside the segment in units of the
+        // segment width.
+        let area = (random - self.cdf[segment]) / (self.cdf[segment + 1] - self.cdf[segment])
+            * (self.efficiency[segment] + self.efficiency[segment + 1])
+            * 0.5;
+        let a = self.efficiency[segment];
+        let slope = self.efficiency[segment + 1] - a;
+
+        // The density is linear within the segment so the inverse is
+        // the root of a second degree polynomial.
+        let x = if slope.abs() < 1e-12 {
+            if a > 0.0 {
+                area / a
+            } else {
+                0.0
+            }
+        } else {
+            (-a + (a * a + 2.0 * slope * area).max(0.0).sqrt()) / slope
+        };
+
+        let t = (segment as f64 + x.clamp(0.0, 1.0)) / segments as f64;
+        self.open + t * (self.close - self.open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_is_uniform() {
+        let shutter = Shutter::try_new(0.0, 2.0, &ShutterCurveConfig::Box).unwrap();
+        assert!((shutter.sample(0.0) - 0.0).abs() < 1e-9);
+        assert!((shutter.sample(0.25) - 0.5).abs() < 1e-9);
+        assert!((shutter.sample(0.5) - 1.0).abs() < 1e-9);
+        assert!((shutter.sample(0.999999) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn triangle_is_centered() {
+        let shutter = Shutter::try_new(0.0, 1.0, &ShutterCurveConfig::Triangle).unwrap();
+        assert!((shutter.sample(0.5) - 0.5).abs() < 1e-9);
+
+        // Half of the area of the first half of the triangle is
+        // covered at 1/sqrt(2) of its width.
+        assert!((shutter.sample(0.25) - 0.5 * std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn custom_curve() {
+        let shutter = Shutter::try_new(
+            1.0,
+            3.0,
+            &ShutterCurveConfig::Custom {
+                samples: vec![0.0, 1.0, 1.0, 0.0],
+            },
+        )
+        .unwrap();
+        assert!((shutter.sample(0.5) - 2.0).abs() < 1e-9);
+        assert!(shutter.sample(0.1) > 1.0);
+        assert!(shutter.sample(0.9) < 3.0);
+    }
+
+    #[test]
+    fn invalid_curves() {
+        assert!(Shutter::try_new(1.0, 0.0, &ShutterCurveConfig::Box).is_err());
+        assert!(Shutter::try_new(
+            0.0,
+            1.0,
+            &ShutterCurveConfig::Custom {
+                samples: vec![0.0, 0.0]
+            }
+        )
+        .is_err());
+        assert!(
+            Shutter::try_new(0.0, 1.0, &ShutterCurveConfig::Custom { samples: vec![1.0] }).is_err()
+        );
+    }
+}
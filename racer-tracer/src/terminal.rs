@@ -1,9 +1,19 @@
+use std::time::{Duration, Instant};
+
 use console::Term;
 use slog::Logger;
 
+use crate::{hud::format_duration, renderer::ProgressInfo};
+
+// How often the progress line is redrawn.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const PROGRESS_BAR_WIDTH: usize = 30;
+
 pub struct Terminal {
     pub logger: Logger,
     pub terminal: Term,
+    last_progress: Instant,
+    showing_progress: bool,
 }
 
 impl Terminal {
@@ -11,8 +21,57 @@ impl Terminal {
         Self {
             logger,
             terminal: Term::stdout(),
+            last_progress: Instant::now(),
+            showing_progress: false,
         }
     }
+
+    // Keeps a progress line at the bottom of the terminal while a
+    // final render is running. Does nothing if the output isn't a
+    // terminal.
+    pub fn update_progress(&mut self, info: &ProgressInfo) {
+        if !self.terminal.features().is_attended() {
+            return;
+        }
+
+        let res = if info.final_render && info.rendering {
+            if self.last_progress.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+            self.last_progress = Instant::now();
+            self.showing_progress = true;
+            self.terminal
+                .clear_line()
+                .and_then(|_| self.terminal.write_str(&progress_line(info)))
+        } else if self.showing_progress {
+            self.showing_progress = false;
+            self.terminal.clear_line()
+        } else {
+            Ok(())
+        };
+
+        if let Err(e) = res {
+            debug!(self.logger, "Failed to write progress: {}", e)
+        }
+    }
+}
+
+fn progress_line(info: &ProgressInfo) -> String {
+    let filled = (info.fraction() * PROGRESS_BAR_WIDTH as f64) as usize;
+    format!(
+        "[{}{}] {:5.1}% Tiles: {}/{} Samples: {:.1}/{} Elapsed: {} ETA: {} {:.2} Mrays/s",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        info.fraction() * 100.0,
+        info.tiles_done,
+        info.tiles,
+        info.samples_done(),
+        info.samples,
+        format_duration(info.elapsed),
+        info.eta()
+            .map_or_else(|| String::from("-"), format_duration),
+        info.rays_per_second() / 1_000_000.0
+    )
 }
 
 macro_rules! write_term {
@@ -4,6 +4,7 @@ use image::ImageBuffer;
 
 use crate::{
     error::TracerError,
+    post_process::output::srgb_to_linear,
     vec3::{Color, Vec3},
 };
 
@@ -11,6 +12,8 @@ use super::Texture;
 
 pub struct TextureImage {
     img: ImageBuffer<img::Rgba<u8>, std::vec::Vec<u8>>,
+    // Images are stored as sRGB. This maps each 8 bit value to linear.
+    linear: [f64; 256],
 }
 
 impl TextureImage {
@@ -21,6 +24,7 @@ impl TextureImage {
             })
             .map(|v| Self {
                 img: v.into_rgba8(),
+                linear: std::array::from_fn(|i| srgb_to_linear(i as f64 / 255.0)),
             })
     }
 }
@@ -42,11 +46,10 @@ impl Texture for TextureImage {
         }
 
         let pixel = self.img.get_pixel(i as u32, j as u32);
-        let color_scale = 1.0 / 255.0;
         Color::new(
-            f64::from(pixel.0[0]) * color_scale,
-            f64::from(pixel.0[1]) * color_scale,
-            f64::from(pixel.0[2]) * color_scale,
+            self.linear[pixel.0[0] as usize],
+            self.linear[pixel.0[1] as usize],
+            self.linear[pixel.0[2] as usize],
         )
     }
 }
@@ -1,14 +1,18 @@
 pub mod aces;
+pub mod agx;
 pub mod hable;
 pub mod none;
+pub mod pbr_neutral;
 pub mod reinhard;
 
 use crate::config::ToneMapConfig;
 use crate::vec3::Color;
 
 use self::aces::Aces;
+use self::agx::Agx;
 use self::hable::{Hable, HableData};
 use self::none::None;
+use self::pbr_neutral::PbrNeutral;
 use self::reinhard::Reinhard;
 
 pub trait ToneMap: Send + Sync {
@@ -60,6 +64,10 @@ impl From<&ToneMapConfig> for Box<dyn ToneMap> {
                 ]);
                 Box::new(Aces::new(input, output)) as Box<dyn ToneMap>
             }
+            ToneMapConfig::Agx { look, .. } => {
+                Box::new(Agx::new(look.clone().unwrap_or_default())) as Box<dyn ToneMap>
+            }
+            ToneMapConfig::PbrNeutral { .. } => Box::new(PbrNeutral::new()) as Box<dyn ToneMap>,
             ToneMapConfig::None => Box::new(None::new()) as Box<dyn ToneMap>,
         }
     }
@@ -0,0 +1,89 @@
+use crate::{config::AgxLook, vec3::Color};
+
+use super::ToneMap;
+
+// Log2 range of the scene that is mapped to the display.
+const MIN_EV: f64 = -12.47393;
+const MAX_EV: f64 = 4.026069;
+
+// Polynomial fit of the AgX base contrast curve.
+fn contrast(x: f64) -> f64 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232
+}
+
+// AgX by Troy Sobotka. Colors are squeezed towards white before the
+// curve so bright saturated colors go to white instead of skewing in
+// hue. Based on the fit used by Blender and three.js.
+pub struct Agx {
+    look: AgxLook,
+    inset: [Color; 3],
+    outset: [Color; 3],
+}
+
+impl Agx {
+    pub fn new(look: AgxLook) -> Self {
+        Self {
+            look,
+            inset: [
+                Color::new(0.842479062253094, 0.0784335999999992, 0.0792237451477643),
+                Color::new(0.0423282422610123, 0.878468636469772, 0.0791661274605434),
+                Color::new(0.0423756549057051, 0.0784336, 0.879142973793104),
+            ],
+            outset: [
+                Color::new(1.19687900512017, -0.0980208811401368, -0.0990297440797205),
+                Color::new(-0.0528968517574562, 1.15190312990417, -0.0989611768448433),
+                Color::new(-0.0529716355144438, -0.0980434501171241, 1.15107367264116),
+            ],
+        }
+    }
+
+    fn mul(matrix: &[Color; 3], color: &Color) -> Color {
+        Color::new(
+            matrix[0].dot(color),
+            matrix[1].dot(color),
+            matrix[2].dot(color),
+        )
+    }
+
+    // ASC CDL slope, power and saturation of the look.
+    fn look(&self, color: Color) -> Color {
+        let (slope, power, saturation) = match self.look {
+            AgxLook::Base => return color,
+            AgxLook::Punchy => (Color::new(1.0, 1.0, 1.0), Color::new(1.35, 1.35, 1.35), 1.4),
+            AgxLook::Golden => (Color::new(1.0, 0.9, 0.5), Color::new(0.8, 0.8, 0.8), 0.8),
+        };
+        let luma = color.dot(&Color::new(0.2126, 0.7152, 0.0722));
+        let graded = Color::new(
+            (color.x() * slope.x()).max(0.0).powf(*power.x()),
+            (color.y() * slope.y()).max(0.0).powf(*power.y()),
+            (color.z() * slope.z()).max(0.0).powf(*power.z()),
+        );
+        (graded - luma) * saturation + luma
+    }
+}
+
+impl ToneMap for Agx {
+    fn tone_map(&self, color: &Color) -> Color {
+        let inset = Agx::mul(&self.inset, color);
+        let curve = |c: f64| {
+            let ev = c.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+            contrast((ev - MIN_EV) / (MAX_EV - MIN_EV))
+        };
+        let encoded = self.look(Color::new(
+            curve(*inset.x()),
+            curve(*inset.y()),
+            curve(*inset.z()),
+        ));
+        // The curve gives display encoded values with a 2.2 gamma. The
+        // output transform expects linear.
+        let outset = Agx::mul(&self.outset, &encoded);
+        Color::new(
+            outset.x().max(0.0).powf(2.2),
+            outset.y().max(0.0).powf(2.2),
+            outset.z().max(0.0).powf(2.2),
+        )
+    }
+}
@@ -0,0 +1,53 @@
+use crate::vec3::Color;
+
+use super::ToneMap;
+
+// Where highlights start to be compressed.
+const START_COMPRESSION: f64 = 0.8 - 0.04;
+const DESATURATION: f64 = 0.15;
+
+// Khronos PBR Neutral. Keeps base colors as they are up to where the
+// highlights are compressed, so materials look like their albedo.
+// https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+pub struct PbrNeutral;
+
+impl PbrNeutral {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ToneMap for PbrNeutral {
+    fn tone_map(&self, color: &Color) -> Color {
+        let x = color.x().min(*color.y()).min(*color.z());
+        let offset = if x < 0.08 { x - 6.25 * x * x } else { 0.04 };
+        let color = *color - offset;
+
+        let peak = color.x().max(*color.y()).max(*color.z());
+        if peak < START_COMPRESSION {
+            return color;
+        }
+
+        let d = 1.0 - START_COMPRESSION;
+        let new_peak = 1.0 - d * d / (peak + d - START_COMPRESSION);
+        let color = color * (new_peak / peak);
+        let g = 1.0 - 1.0 / (DESATURATION * (peak - new_peak) + 1.0);
+        color * (1.0 - g) + Color::new(new_peak, new_peak, new_peak) * g
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_highlights_only() {
+        let tone_map = PbrNeutral::new();
+        let color = tone_map.tone_map(&Color::new(0.5, 0.3, 0.2));
+        assert!((color - Color::new(0.46, 0.26, 0.16)).length() < 1e-9);
+
+        let bright = tone_map.tone_map(&Color::new(100.0, 50.0, 10.0));
+        assert!(*bright.x() <= 1.0 && *bright.x() > 0.99);
+        assert!(bright.z() < bright.x());
+    }
+}
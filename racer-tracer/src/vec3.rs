@@ -3,13 +3,13 @@ use std::{
     ops::{self, Index},
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::util::{random_double, random_double_range};
 
 //https://doc.rust-lang.org/core/arch/x86_64/struct.__m128.html
 //https://doc.rust-lang.org/core/arch/x86_64/fn._mm_mul_ps.html
-#[derive(Default, Clone, Copy, Deserialize)]
+#[derive(Default, Clone, Copy, Deserialize, Serialize)]
 pub struct Vec3 {
     #[serde(alias = "color")]
     pos: [f64; 3],
@@ -18,7 +18,7 @@ pub struct Vec3 {
 pub type Color = Vec3;
 
 impl Vec3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3 {
         Vec3 { pos: [x, y, z] }
     }
 
@@ -116,12 +116,9 @@ impl Vec3 {
         }
     }
 
-    pub fn scale_sqrt(mut self, samples: usize) -> Vec3 {
-        let scale = 1.0 / samples as f64;
-        self.pos[0] = (scale * self.pos[0]).sqrt();
-        self.pos[1] = (scale * self.pos[1]).sqrt();
-        self.pos[2] = (scale * self.pos[2]).sqrt();
-        self
+    // Average of the sum of samples.
+    pub fn average(self, samples: usize) -> Vec3 {
+        self / samples as f64
     }
 
     pub fn near_zero(&self) -> bool {
@@ -0,0 +1,345 @@
+use std::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+use slog::Logger;
+use synchronoise::SignalEvent;
+
+use crate::{
+    background_color::BackgroundColor,
+    bvh_node::BoundingVolumeHirearchy,
+    camera::{Camera, CameraData, CameraInitData, CameraSharedData, CameraState},
+    config::{Config, SceneLoaderConfig},
+    error::TracerError,
+    image::Image,
+    renderer::{
+        cpu::CpuRenderer,
+        distributed::{read_message, write_message, Message},
+        RenderData, RenderProgress,
+    },
+    scene::{place_objects, yml::YmlLoader, PlacedObject, Scene, SceneLoader},
+};
+
+// Everything needed to render tiles of the coordinator's image.
+struct WorkerScene {
+    config: Config,
+    image: Image,
+    camera_data: CameraSharedData,
+    bvh: BoundingVolumeHirearchy,
+    background: Box<dyn BackgroundColor>,
+}
+
+impl WorkerScene {
+    fn load(
+        config: Config,
+        scene: Option<String>,
+        objects: Option<Vec<PlacedObject>>,
+        state: &CameraState,
+    ) -> Result<Self, TracerError> {
+        let image = Image::new(config.screen.width, config.screen.height);
+        let loader: Box<dyn SceneLoader> = match (scene, &config.loader) {
+            (Some(source), SceneLoaderConfig::Yml { path }) => {
+                Box::new(YmlLoader::from_source(path.clone(), source))
+            }
+            _ => (&config).into(),
+        };
+        let scene_data = loader.load()?;
+
+        let camera_data =
+            CameraData::merge(scene_data.camera.unwrap_or_default(), config.camera.clone());
+        let camera_init = CameraInitData::try_new(&camera_data, &image)?;
+        let shutter = camera_init.shutter.clone();
+        let mut camera = Camera::new(camera_init, &image);
+        camera.set_state(state)?;
+
+        let objects = match objects {
+            Some(placed) => place_objects(&scene_data.objects, &placed)?,
+            None => scene_data.objects,
+        };
+        let mut scene = Scene::new(camera.get_shared_camera(), image.clone(), objects);
+        let (objs, reader) = scene.get_shared_objects();
+        Ok(Self {
+            camera_data: camera.get_shared_camera().data().clone(),
+            bvh: BoundingVolumeHirearchy::new(objs, reader, shutter.open(), shutter.close()),
+            background: scene_data.background,
+            config,
+            image,
+        })
+    }
+}
+
+// Renders tiles for coordinators until the process is stopped.
+pub fn run_worker(log: Logger, listen: &str) -> Result<(), TracerError> {
+    let listener = TcpListener::bind(listen)
+        .map_err(|e| TracerError::Distributed(format!("Failed to listen on {}: {}", listen, e)))?;
+    info!(log, "Listening for tiles on {}", listen);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let log = log.new(o!("coordinator" => stream
+                    .peer_addr()
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default()));
+                std::thread::spawn(move || {
+                    info!(log, "Connected.");
+                    match serve(&log, stream) {
+                        Ok(_) => info!(log, "Disconnected."),
+                        Err(e) => warn!(log, "Disconnected: {}", e),
+                    }
+                });
+            }
+            Err(e) => warn!(log, "Failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn serve(log: &Logger, stream: TcpStream) -> Result<(), TracerError> {
+    let error = |e: std::io::Error| TracerError::Distributed(e.to_string());
+    stream.set_nodelay(true).map_err(error)?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(error)?);
+    let writer = Mutex::new(stream);
+    let reply = |message: &Message| {
+        writer
+            .lock()
+            .map_err(|e| TracerError::FailedToAcquireLock(format!("worker-stream: {}", e)))
+            .and_then(|mut stream| write_message(&mut *stream, message))
+    };
+
+    let scene = match read_message(&mut reader)? {
+        Message::Setup {
+            config,
+            scene,
+            camera,
+            objects,
+        } => WorkerScene::load(*config, scene, objects, &camera),
+        _ => Err(TracerError::Distributed(
+            "Expected the scene to be set up first".to_string(),
+        )),
+    }
+    .or_else(|e| {
+        reply(&Message::Failed {
+            error: e.to_string(),
+        })
+        .and(Err(e))
+    })?;
+    reply(&Message::Ready {
+        threads: rayon::current_num_threads(),
+    })?;
+    info!(log, "Scene loaded, waiting for tiles.");
+
+    let renderer = CpuRenderer::new(scene.config.render.clone());
+    let progress = RenderProgress::new();
+    // Tiles still being rendered are pointless once the coordinator
+    // is gone.
+    let disconnected = SignalEvent::manual(false);
+    let rd = RenderData {
+        camera_data: &scene.camera_data,
+        image: &scene.image,
+        scene: &scene.bvh,
+        background: &*scene.background,
+        config: &scene.config,
+        cancel_event: Some(&disconnected),
+        progress: &progress,
+        region: None,
+        checkpoint: None,
+        objects: None,
+    };
+
+    // The connection is read here while the tiles are rendered on the
+    // rayon threads.
+    rayon::in_place_scope(|s| loop {
+        match read_message(&mut reader) {
+            Ok(Message::Tile { id, tile }) => {
+                let (rd, renderer, reply) = (&rd, &renderer, &reply);
+                s.spawn(move |_| {
+                    let res = match renderer.trace(rd, rd.camera_data, &tile) {
+                        Ok(Some((rgb, obj_id))) => {
+                            let (rays, node_visits) = rd.progress.take_rays();
+                            reply(&Message::TileDone {
+                                id,
+                                rgb,
+                                obj_id,
+                                rays,
+                                node_visits,
+                            })
+                        }
+                        Ok(None) => Ok(()),
+                        Err(e) => reply(&Message::Failed {
+                            error: e.to_string(),
+                        }),
+                    };
+                    if let Err(e) = res {
+                        warn!(log, "Failed to send tile {}: {}", id, e);
+                    }
+                });
+            }
+            Ok(_) => {
+                disconnected.signal();
+                break Err(TracerError::Distributed(
+                    "Expected a tile to render".to_string(),
+                ));
+            }
+            // The coordinator closes the connection when it's done.
+            Err(_) => {
+                disconnected.signal();
+                break Ok(());
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{CameraConfig, RenderConfig, ScreenConfig},
+        data_bus::DataBus,
+        image_buffer::ImageBufferEvent,
+        renderer::{distributed::DistributedRenderer, Renderer},
+        scene::ObjectCookie,
+        vec3::{Color, Vec3},
+    };
+
+    use super::*;
+
+    // Object ids and colors of everything written to the bus.
+    fn rendered(
+        bus: &mut DataBus<ImageBufferEvent>,
+        image: &Image,
+        mut reader: crate::data_bus::DataReader<ImageBufferEvent>,
+    ) -> (Vec<usize>, Vec<Color>) {
+        let mut obj_ids = vec![0; image.width * image.height];
+        let mut colors = vec![Color::default(); image.width * image.height];
+        bus.update().unwrap();
+        for event in reader.get_messages().unwrap() {
+            match event {
+                ImageBufferEvent::ObjectIdUpdate {
+                    obj_id,
+                    r,
+                    c,
+                    width,
+                    ..
+                } => obj_id.iter().enumerate().for_each(|(i, id)| {
+                    obj_ids[(r + i / width) * image.width + c + i % width] = *id
+                }),
+                ImageBufferEvent::BufferUpdate {
+                    rgb, r, c, width, ..
+                } => rgb.iter().enumerate().for_each(|(i, color)| {
+                    colors[(r + i / width) * image.width + c + i % width] = *color
+                }),
+            }
+        }
+        (obj_ids, colors)
+    }
+
+    // The random scene with the center sphere removed and another
+    // moved in its place, rendered here and on a worker.
+    #[test]
+    fn worker_renders_the_edited_scene() {
+        let log = Logger::root(slog::Discard, o!());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let worker_log = log.clone();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve(&worker_log, stream)
+        });
+
+        let config = Config {
+            loader: SceneLoaderConfig::Random,
+            scene_seed: Some(7),
+            screen: ScreenConfig {
+                width: 64,
+                height: 48,
+                hud: false,
+            },
+            render: RenderConfig {
+                samples: 8,
+                max_depth: 4,
+                scale: 1,
+                tile_size: 8,
+            },
+            camera: CameraConfig {
+                aperture: Some(0.0),
+                ..CameraConfig::default()
+            },
+            ..Config::default()
+        };
+        let image = Image::new(config.screen.width, config.screen.height);
+        let scene_data = Box::<dyn SceneLoader>::from(&config).load().unwrap();
+        let camera_data =
+            CameraData::merge(scene_data.camera.unwrap_or_default(), config.camera.clone());
+        let mut camera = Camera::new(
+            CameraInitData::try_new(&camera_data, &image).unwrap(),
+            &image,
+        );
+        let mut scene = Scene::new(
+            camera.get_shared_camera(),
+            image.clone(),
+            scene_data.objects,
+        );
+        let (objs, reader) = scene.get_shared_objects();
+        let mut bvh = BoundingVolumeHirearchy::new(objs, reader, 0.0, 1.0);
+
+        let center = scene.objects().len() - 3;
+        let removed = scene.objects()[center].id();
+        scene.remove_object(&ObjectCookie { id: center }).unwrap();
+        let moved = scene.objects()[center].id();
+        scene
+            .set_pos(&ObjectCookie { id: center }, Vec3::new(0.0, 1.0, 0.0))
+            .unwrap();
+        scene.update().unwrap();
+        bvh.update().unwrap();
+        let placed = scene.placed_objects();
+        let shared_camera = camera.get_shared_camera();
+
+        let progress = RenderProgress::new();
+        let render = |renderer: &dyn Renderer| {
+            let mut bus = DataBus::<ImageBufferEvent>::new("test");
+            let reader = bus.get_reader();
+            renderer
+                .render(
+                    RenderData {
+                        camera_data: shared_camera.data(),
+                        image: &image,
+                        scene: &bvh,
+                        background: &*scene_data.background,
+                        config: &config,
+                        cancel_event: None,
+                        progress: &progress,
+                        region: None,
+                        checkpoint: None,
+                        objects: Some(&placed),
+                    },
+                    &bus.get_writer(),
+                )
+                .unwrap();
+            rendered(&mut bus, &image, reader)
+        };
+        let (local_ids, local_colors) = render(&CpuRenderer::new(config.render.clone()));
+        let (worker_ids, worker_colors) = render(&DistributedRenderer::new(
+            config.render.clone(),
+            vec![address],
+            Some(30),
+            log,
+        ));
+
+        assert!(!worker_ids.contains(&removed));
+        assert!(worker_ids.contains(&moved));
+        // Lots of small spheres so plenty of edges where the jitter of the
+        // samples can hit another object.
+        let same = local_ids
+            .iter()
+            .zip(worker_ids.iter())
+            .filter(|(a, b)| a == b)
+            .count();
+        assert!(same * 4 >= local_ids.len() * 3);
+        let mean = |colors: &[Color]| {
+            colors.iter().fold(Color::default(), |sum, c| sum + *c) / colors.len() as f64
+        };
+        assert!((mean(&local_colors) - mean(&worker_colors)).length() < 0.05);
+    }
+}